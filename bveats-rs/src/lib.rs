@@ -1,6 +1,8 @@
 #![allow(unused)]
 use std::os::raw::*;
 
+pub mod record;
+
 /// GetPluginVersion() の戻り値を表す。
 pub const ATS_VERSION: c_int = 0x00020000;
 
@@ -281,17 +283,39 @@ pub trait BveAts {
 }
 
 /// BveAtsトレイト からネイティブAPIへの変換を行うマクロ
+///
+/// 2番目の引数に `Option<PathBuf>` を返す式を渡すと、Load時に評価し、
+/// `Some` の場合はそのパスに全ての呼び出しを記録する。
+/// 3番目の引数に `String` を返す式を渡すと、記録する場合のみ評価して記録ファイルのヘッダに書き込む。
 #[macro_export]
 macro_rules! ats_main {
     ($t: ty) => {
+        ::bveats_rs::ats_main!($t, ::std::option::Option::<::std::path::PathBuf>::None);
+    };
+    ($t: ty, $record_path: expr) => {
+        ::bveats_rs::ats_main!($t, $record_path, ::std::string::String::new());
+    };
+    ($t: ty, $record_path: expr, $record_header: expr) => {
         use ::std::sync::Mutex;
         use ::std::sync::OnceLock;
         use ::std::os::raw::*;
         use ::bveats_rs::*;
+        use ::bveats_rs::record::{AtsCall, SessionRecorder};
         static ATS: OnceLock<Mutex<$t>> = OnceLock::new();
+        static RECORDER: Mutex<Option<SessionRecorder>> = Mutex::new(None);
+
+        fn record_call(call: AtsCall) {
+            if let Some(recorder) = RECORDER.lock().expect("Mutex error: at record_call()").as_mut() {
+                recorder.record(call);
+            }
+        }
 
         #[no_mangle]
         pub unsafe extern "system" fn Load() {
+            if let Some(path) = $record_path {
+                *RECORDER.lock().expect("Mutex error: at Load()") = SessionRecorder::create_with_header(&path, &$record_header).ok();
+            }
+            record_call(AtsCall::Load);
             ATS .get_or_init(|| Mutex::new(<$t>::default()))
                 .lock()
                 .expect("Mutex error: at Load()")
@@ -300,15 +324,20 @@ macro_rules! ats_main {
 
         #[no_mangle]
         pub unsafe extern "system" fn Dispose() {
+            record_call(AtsCall::Dispose);
             ATS .get()
                 .expect("OnceLock error: at Dispose()")
                 .lock()
                 .expect("Mutex error: at Dispose()")
-                .dispose()
+                .dispose();
+            if let Some(recorder) = RECORDER.lock().expect("Mutex error: at Dispose()").as_mut() {
+                recorder.flush();
+            }
         }
 
         #[no_mangle]
         pub unsafe extern "system" fn GetPluginVersion() -> c_int {
+            record_call(AtsCall::GetPluginVersion);
             ATS .get()
                 .expect("OnceLock error: at GetPluginVersion()")
                 .lock()
@@ -318,6 +347,7 @@ macro_rules! ats_main {
 
         #[no_mangle]
         pub unsafe extern "system" fn SetVehicleSpec(spec: AtsVehicleSpec) {
+            record_call(AtsCall::SetVehicleSpec(spec));
             ATS .get()
                 .expect("OnceLock error: at SetVehicleSpec()")
                 .lock()
@@ -327,6 +357,7 @@ macro_rules! ats_main {
 
         #[no_mangle]
         pub unsafe extern "system" fn Initialize(brake: c_int) {
+            record_call(AtsCall::Initialize(brake));
             ATS .get()
                 .expect("OnceLock error: at Initialize()")
                 .lock()
@@ -336,17 +367,21 @@ macro_rules! ats_main {
 
         #[no_mangle]
         pub unsafe extern "system" fn Elapse(state: AtsVehicleState, panel: *mut c_int, sound: *mut c_int) -> AtsHandles {
-            ATS .get()
+            let panel = std::slice::from_raw_parts_mut(panel, 256);
+            let sound = std::slice::from_raw_parts_mut(sound, 256);
+            let mut ats = ATS .get()
                 .expect("OnceLock error: at Elapse()")
                 .lock()
-                .expect("Mutex error: at Elapse()")
-                .elapse(state, 
-                    std::slice::from_raw_parts_mut(panel, 256), 
-                    std::slice::from_raw_parts_mut(sound, 256))
+                .expect("Mutex error: at Elapse()");
+            match RECORDER.lock().expect("Mutex error: at Elapse()").as_mut() {
+                Some(recorder) => recorder.record_elapse(state, panel, sound, |state, panel, sound| ats.elapse(state, panel, sound)),
+                None => ats.elapse(state, panel, sound),
+            }
         }
 
         #[no_mangle]
         pub unsafe extern "system" fn SetPower(notch: c_int) {
+            record_call(AtsCall::SetPower(notch));
             ATS .get()
                 .expect("OnceLock error: at SetPower()")
                 .lock()
@@ -356,6 +391,7 @@ macro_rules! ats_main {
 
         #[no_mangle]
         pub unsafe extern "system" fn SetBrake(notch: c_int) {
+            record_call(AtsCall::SetBrake(notch));
             ATS .get()
                 .expect("OnceLock error: at SetBrake()")
                 .lock()
@@ -365,6 +401,7 @@ macro_rules! ats_main {
 
         #[no_mangle]
         pub unsafe extern "system" fn SetReverser(notch: c_int) {
+            record_call(AtsCall::SetReverser(notch));
             ATS .get()
                 .expect("OnceLock error: at SetReverser()")
                 .lock()
@@ -374,6 +411,7 @@ macro_rules! ats_main {
 
         #[no_mangle]
         pub unsafe extern "system" fn KeyDown(key: c_int) {
+            record_call(AtsCall::KeyDown(key));
             ATS .get()
                 .expect("OnceLock error: at KeyDown()")
                 .lock()
@@ -383,6 +421,7 @@ macro_rules! ats_main {
 
         #[no_mangle]
         pub unsafe extern "system" fn KeyUp(key: c_int) {
+            record_call(AtsCall::KeyUp(key));
             ATS .get()
                 .expect("OnceLock error: at KeyUp()")
                 .lock()
@@ -392,6 +431,7 @@ macro_rules! ats_main {
 
         #[no_mangle]
         pub unsafe extern "system" fn HornBlow(horn: c_int) {
+            record_call(AtsCall::HornBlow(horn));
             ATS .get()
                 .expect("OnceLock error: at HornBlow()")
                 .lock()
@@ -401,6 +441,7 @@ macro_rules! ats_main {
 
         #[no_mangle]
         pub unsafe extern "system" fn DoorOpen() {
+            record_call(AtsCall::DoorOpen);
             ATS .get()
                 .expect("OnceLock error: at DoorOpen()")
                 .lock()
@@ -410,6 +451,7 @@ macro_rules! ats_main {
 
         #[no_mangle]
         pub unsafe extern "system" fn DoorClose() {
+            record_call(AtsCall::DoorClose);
             ATS .get()
                 .expect("OnceLock error: at DoorClose()")
                 .lock()
@@ -419,6 +461,7 @@ macro_rules! ats_main {
 
         #[no_mangle]
         pub unsafe extern "system" fn SetSignal(signal: c_int) {
+            record_call(AtsCall::SetSignal(signal));
             ATS .get()
                 .expect("OnceLock error: at SetSignal()")
                 .lock()
@@ -428,6 +471,7 @@ macro_rules! ats_main {
 
         #[no_mangle]
         pub unsafe extern "system" fn SetBeaconData(data: AtsBeaconData) {
+            record_call(AtsCall::SetBeaconData(data));
             ATS .get()
                 .expect("OnceLock error: at SetBeaconData()")
                 .lock()
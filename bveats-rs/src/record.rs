@@ -0,0 +1,293 @@
+//! BVEから呼び出された関数の記録と再生
+
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::*;
+
+/// BVEから呼び出される関数とその引数
+#[derive(Debug, Clone, Copy)]
+pub enum AtsCall {
+    Load,
+    Dispose,
+    GetPluginVersion,
+    SetVehicleSpec(AtsVehicleSpec),
+    Initialize(c_int),
+    SetPower(c_int),
+    SetBrake(c_int),
+    SetReverser(c_int),
+    KeyDown(c_int),
+    KeyUp(c_int),
+    HornBlow(c_int),
+    DoorOpen,
+    DoorClose,
+    SetSignal(c_int),
+    SetBeaconData(AtsBeaconData),
+}
+impl AtsCall {
+    /// 記録ファイルの1行に変換する関数
+    fn to_line(self) -> String {
+        match self {
+            Self::Load => "L".to_string(),
+            Self::Dispose => "X".to_string(),
+            Self::GetPluginVersion => "G".to_string(),
+            Self::SetVehicleSpec(spec) => format!("V {} {} {} {} {}",
+                spec.brake_notches, spec.power_notches, spec.ats_notch, spec.b67_notch, spec.cars),
+            Self::Initialize(brake) => format!("I {brake}"),
+            Self::SetPower(notch) => format!("P {notch}"),
+            Self::SetBrake(notch) => format!("B {notch}"),
+            Self::SetReverser(notch) => format!("R {notch}"),
+            Self::KeyDown(key) => format!("KD {key}"),
+            Self::KeyUp(key) => format!("KU {key}"),
+            Self::HornBlow(horn) => format!("H {horn}"),
+            Self::DoorOpen => "DO".to_string(),
+            Self::DoorClose => "DC".to_string(),
+            Self::SetSignal(signal) => format!("S {signal}"),
+            Self::SetBeaconData(data) => format!("BD {} {} {} {}",
+                data.beacon_type, data.signal, data.distance, data.optional),
+        }
+    }
+
+    /// プラグインの対応する関数を呼び出す関数
+    pub fn apply<T: BveAts>(self, ats: &mut T) {
+        match self {
+            Self::Load => ats.load(),
+            Self::Dispose => ats.dispose(),
+            Self::GetPluginVersion => { ats.get_plugin_version(); }
+            Self::SetVehicleSpec(spec) => ats.set_vehicle_spec(spec),
+            Self::Initialize(brake) => ats.initialize(AtsInit::from(brake)),
+            Self::SetPower(notch) => ats.set_power(notch),
+            Self::SetBrake(notch) => ats.set_brake(notch),
+            Self::SetReverser(notch) => ats.set_reverser(notch),
+            Self::KeyDown(key) => ats.key_down(AtsKey::from(key)),
+            Self::KeyUp(key) => ats.key_up(AtsKey::from(key)),
+            Self::HornBlow(horn) => ats.horn_blow(AtsHorn::from(horn)),
+            Self::DoorOpen => ats.door_open(),
+            Self::DoorClose => ats.door_close(),
+            Self::SetSignal(signal) => ats.set_signal(signal),
+            Self::SetBeaconData(data) => ats.set_beacon_data(data),
+        }
+    }
+}
+
+/// panel, sound の差分を "p<index>=<value> s<index>=<value>" の形式で書き出す
+fn write_diff(line: &mut String, before_panel: &[i32], panel: &[i32], before_sound: &[i32], sound: &[i32]) {
+    for (i, (before, after)) in before_panel.iter().zip(panel).enumerate() {
+        if before != after {
+            let _ = write!(line, " p{i}={after}");
+        }
+    }
+    for (i, (before, after)) in before_sound.iter().zip(sound).enumerate() {
+        if before != after {
+            let _ = write!(line, " s{i}={after}");
+        }
+    }
+}
+
+/// BVEから呼び出された関数を記録ファイルに書き出す
+///
+/// 1行に1回の呼び出しを記録する。Elapseは
+/// `E <状態量> | <呼び出し前のpanel/soundの差分> | <ハンドル出力> | <呼び出し後のpanel/soundの差分>`
+/// の形式で記録する。先頭の `# ` で始まる行はヘッダ(記録時の設定など)で、再生時は無視する。
+pub struct SessionRecorder {
+    writer: BufWriter<File>,
+    panel: Vec<i32>,
+    sound: Vec<i32>,
+}
+
+impl SessionRecorder {
+    /// 記録ファイルを作成する関数
+    pub fn create(path: &Path) -> std::io::Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+            panel: vec![0; 256],
+            sound: vec![0; 256],
+        })
+    }
+
+    /// ヘッダを書き込んで記録ファイルを作成する関数
+    pub fn create_with_header(path: &Path, header: &str) -> std::io::Result<Self> {
+        let mut recorder = Self::create(path)?;
+        for line in header.lines() {
+            writeln!(recorder.writer, "# {line}")?;
+        }
+        recorder.writer.flush()?;
+        Ok(recorder)
+    }
+
+    /// Elapse以外の呼び出しを記録する関数
+    pub fn record(&mut self, call: AtsCall) {
+        let _ = writeln!(self.writer, "{}", call.to_line());
+        let _ = self.writer.flush();
+    }
+
+    /// Elapseを呼び出し、その入出力を記録する関数
+    pub fn record_elapse(&mut self, state: AtsVehicleState, panel: &mut [i32], sound: &mut [i32],
+        elapse: impl FnOnce(AtsVehicleState, &mut [i32], &mut [i32]) -> AtsHandles) -> AtsHandles {
+        let mut line = format!("E {} {} {} {} {} {} {} {} {} |",
+            state.location, state.speed, state.time, state.bc_pressure, state.mr_pressure,
+            state.er_pressure, state.bp_pressure, state.sap_pressure, state.current);
+        write_diff(&mut line, &self.panel, panel, &self.sound, sound);
+        self.panel.copy_from_slice(panel);
+        self.sound.copy_from_slice(sound);
+
+        let handles = elapse(state, panel, sound);
+
+        let _ = write!(line, " | {} {} {} {} |", handles.brake, handles.power, handles.reverser, handles.constant_speed);
+        write_diff(&mut line, &self.panel, panel, &self.sound, sound);
+        self.panel.copy_from_slice(panel);
+        self.sound.copy_from_slice(sound);
+
+        let _ = writeln!(self.writer, "{line}");
+        handles
+    }
+
+    /// 記録ファイルを書き出す関数
+    pub fn flush(&mut self) {
+        let _ = self.writer.flush();
+    }
+}
+
+/// 再生時に記録と異なった箇所
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayMismatch {
+    /// 記録ファイルの行番号
+    pub line: usize,
+    /// Elapseの時刻 [ms]
+    pub time: i32,
+    /// 異なった内容
+    pub message: String,
+}
+
+/// 記録ファイルのヘッダを取得する関数 (ヘッダがない場合はNone)
+pub fn read_header(log: &str) -> Option<String> {
+    let lines: Vec<&str> = log.lines()
+        .map_while(|line| line.strip_prefix("# ").or(if line == "#" { Some("") } else { None }))
+        .collect();
+    (!lines.is_empty()).then(|| lines.iter().map(|line| format!("{line}\n")).collect())
+}
+
+fn parse_value<V: std::str::FromStr>(value: Option<&str>, line: usize) -> Result<V, String> {
+    value.and_then(|value| value.parse().ok()).ok_or(format!("{line} 行目: 値が不正です。"))
+}
+
+/// "p<index>=<value> s<index>=<value>" の形式の差分を配列に適用する
+fn apply_diff(diff: &str, panel: &mut [i32], sound: &mut [i32], line: usize) -> Result<(), String> {
+    for item in diff.split_whitespace() {
+        let (array, item) = match item.split_at_checked(1) {
+            Some(("p", item)) => (&mut *panel, item),
+            Some(("s", item)) => (&mut *sound, item),
+            _ => return Err(format!("{line} 行目: 差分が不正です。({item})")),
+        };
+        let (index, value) = item.split_once('=').ok_or(format!("{line} 行目: 差分が不正です。({item})"))?;
+        let index: usize = parse_value(Some(index), line)?;
+        if index >= array.len() {
+            return Err(format!("{line} 行目: インデックスが範囲外です。({index})"));
+        }
+        array[index] = parse_value(Some(value), line)?;
+    }
+    Ok(())
+}
+
+/// 記録ファイルをBveAtsの実装に再生し、記録と異なった出力を返す関数
+pub fn replay_session<T: BveAts>(ats: &mut T, log: &str) -> Result<Vec<ReplayMismatch>, String> {
+    let mut mismatches = Vec::new();
+    let mut panel = vec![0; 256];
+    let mut sound = vec![0; 256];
+    let mut expected_panel = vec![0; 256];
+    let mut expected_sound = vec![0; 256];
+
+    for (i, text) in log.lines().enumerate() {
+        let line = i + 1;
+        if text.trim().is_empty() || text.starts_with('#') {
+            continue;
+        }
+        if let Some(text) = text.strip_prefix("E ") {
+            let sections: Vec<&str> = text.split('|').collect();
+            if sections.len() != 4 {
+                return Err(format!("{line} 行目: Elapseの形式が不正です。"));
+            }
+            let mut values = sections[0].split_whitespace();
+            let state = AtsVehicleState {
+                location: parse_value(values.next(), line)?,
+                speed: parse_value(values.next(), line)?,
+                time: parse_value(values.next(), line)?,
+                bc_pressure: parse_value(values.next(), line)?,
+                mr_pressure: parse_value(values.next(), line)?,
+                er_pressure: parse_value(values.next(), line)?,
+                bp_pressure: parse_value(values.next(), line)?,
+                sap_pressure: parse_value(values.next(), line)?,
+                current: parse_value(values.next(), line)?,
+            };
+            // BVEが渡したpanel/soundを再現する
+            apply_diff(sections[1], &mut panel, &mut sound, line)?;
+            apply_diff(sections[1], &mut expected_panel, &mut expected_sound, line)?;
+
+            let mut values = sections[2].split_whitespace();
+            let expected = AtsHandles {
+                brake: parse_value(values.next(), line)?,
+                power: parse_value(values.next(), line)?,
+                reverser: parse_value(values.next(), line)?,
+                constant_speed: parse_value(values.next(), line)?,
+            };
+            apply_diff(sections[3], &mut expected_panel, &mut expected_sound, line)?;
+
+            let handles = ats.elapse(state, &mut panel, &mut sound);
+
+            let mut mismatch = |message: String| mismatches.push(ReplayMismatch { line, time: state.time, message });
+            if (handles.brake, handles.power, handles.reverser, handles.constant_speed)
+                != (expected.brake, expected.power, expected.reverser, expected.constant_speed) {
+                mismatch(format!("handles: 記録 {expected:?} / 再生 {handles:?}"));
+            }
+            for (index, (expected, actual)) in expected_panel.iter().zip(&panel).enumerate() {
+                if expected != actual {
+                    mismatch(format!("panel[{index}]: 記録 {expected} / 再生 {actual}"));
+                }
+            }
+            for (index, (expected, actual)) in expected_sound.iter().zip(&sound).enumerate() {
+                if expected != actual {
+                    mismatch(format!("sound[{index}]: 記録 {expected} / 再生 {actual}"));
+                }
+            }
+            // 以降の比較は記録された値を基準にする
+            panel.copy_from_slice(&expected_panel);
+            sound.copy_from_slice(&expected_sound);
+            continue;
+        }
+
+        let mut values = text.split_whitespace();
+        let call = match values.next() {
+            Some("L") => AtsCall::Load,
+            Some("X") => AtsCall::Dispose,
+            Some("G") => AtsCall::GetPluginVersion,
+            Some("V") => AtsCall::SetVehicleSpec(AtsVehicleSpec {
+                brake_notches: parse_value(values.next(), line)?,
+                power_notches: parse_value(values.next(), line)?,
+                ats_notch: parse_value(values.next(), line)?,
+                b67_notch: parse_value(values.next(), line)?,
+                cars: parse_value(values.next(), line)?,
+            }),
+            Some("I") => AtsCall::Initialize(parse_value(values.next(), line)?),
+            Some("P") => AtsCall::SetPower(parse_value(values.next(), line)?),
+            Some("B") => AtsCall::SetBrake(parse_value(values.next(), line)?),
+            Some("R") => AtsCall::SetReverser(parse_value(values.next(), line)?),
+            Some("KD") => AtsCall::KeyDown(parse_value(values.next(), line)?),
+            Some("KU") => AtsCall::KeyUp(parse_value(values.next(), line)?),
+            Some("H") => AtsCall::HornBlow(parse_value(values.next(), line)?),
+            Some("DO") => AtsCall::DoorOpen,
+            Some("DC") => AtsCall::DoorClose,
+            Some("S") => AtsCall::SetSignal(parse_value(values.next(), line)?),
+            Some("BD") => AtsCall::SetBeaconData(AtsBeaconData {
+                beacon_type: parse_value(values.next(), line)?,
+                signal: parse_value(values.next(), line)?,
+                distance: parse_value(values.next(), line)?,
+                optional: parse_value(values.next(), line)?,
+            }),
+            _ => return Err(format!("{line} 行目: 不明な呼び出しです。({text})")),
+        };
+        call.apply(ats);
+    }
+    Ok(mismatches)
+}
@@ -1,4 +1,7 @@
 pub mod uline_atc;
 pub mod atc_signal;
 pub mod auto_brake;
-pub mod speed_control;
\ No newline at end of file
+pub mod speed_control;
+pub mod pattern;
+pub mod door_interlock;
+pub mod gradient;
@@ -1,8 +1,11 @@
+use serde::{Deserialize, Serialize};
+
 /// ATC信号を表す
 #[allow(dead_code)]
 #[repr(u8)]
 #[derive(Clone, Copy)]
 #[derive(PartialEq, Debug)]
+#[derive(Serialize, Deserialize)]
 pub enum AtcSignal {
     /// 02信号(絶対停止)
     Signal02 = 0,
@@ -40,6 +43,10 @@ impl AtcSignal {
             Self::Irekae25 => 25,
         }
     }
+    /// 入換信号か
+    pub fn is_irekae(&self) -> bool {
+        matches!(self, Self::Irekae15 | Self::Irekae25)
+    }
     pub fn getLower(&self) -> Self {
         match self {
             Self::Signal02 => Self::Signal02,
@@ -54,6 +61,7 @@ impl AtcSignal {
         }
     }
 }
+#[allow(clippy::derivable_impls)]
 impl Default for AtcSignal {
     fn default() -> Self {
         Self::Signal02
@@ -1,8 +1,9 @@
 use bveats_rs::{AtsConstantSpeed, AtsHandles, AtsSound, AtsVehicleState};
+use log::warn;
 
-use super::{atc_signal::AtcSignal, uline_atc::{AtcBrakeStatus, ULineATC}};
+use crate::{output::SoundOutput, settings::HisetsuBrake};
 
-const ATS_SOUND_BUZZER: usize = 3;
+use super::{atc_signal::AtcSignal, uline_atc::{AtcBrakeStatus, ULineATC}};
 
 /// ATCブレーキなし状態のAtsHandlesを取得
 fn get_none_brake_handle(_atc: &ULineATC, handles: AtsHandles) -> AtsHandles {
@@ -10,7 +11,7 @@ fn get_none_brake_handle(_atc: &ULineATC, handles: AtsHandles) -> AtsHandles {
 }
 /// ATC緩和ブレーキ状態のAtsHandlesを取得
 fn get_half_brake_handle(_atc: &ULineATC, mut handles: AtsHandles) -> AtsHandles {
-	handles.brake = _atc.settings.vehicle.output_brake_notches / 2;
+	handles.brake = _atc.settings.vehicle.get_half_brake_notch();
 	handles.constant_speed = AtsConstantSpeed::Disable as i32;
 	handles
 }
@@ -35,7 +36,7 @@ fn enable_atc_brake(signal_speed: f32, vehicle_speed: f32) -> bool {
 /// ATC有効時にElapse内のATCブレーキ判定を行う関数
 pub fn elapse_atc_brake<'a>(atc: &'a mut ULineATC, handles: AtsHandles, state: AtsVehicleState, sound: &'a mut [i32]) -> AtsHandles {
 
-	let enable_auto_brake = enable_atc_brake(atc.now_signal.getSpeed() as f32 + atc.settings.atc.check_speed_margin, state.speed);
+	let enable_auto_brake = enable_atc_brake(atc.get_limit_speed() + atc.settings.atc.check_speed_margin, state.speed);
 	// ブレーキが掛かった瞬間
 	if atc.atc_brake_status == AtcBrakeStatus::Passing && enable_auto_brake {
 		atc.atc_brake_status = AtcBrakeStatus::HalfBraking(state.time);
@@ -92,12 +93,13 @@ pub fn elapse_atc_brake<'a>(atc: &'a mut ULineATC, handles: AtsHandles, state: A
 
 	match atc.atc_brake_status {
 		AtcBrakeStatus::Passing => {
-			sound[ATS_SOUND_BUZZER] = AtsSound::Stop as i32;
+			atc.output.set_sound(sound, SoundOutput::AtcBuzzer, AtsSound::Stop as i32);
 		},
 		_ => {
-			sound[ATS_SOUND_BUZZER] = AtsSound::PlayLooping as i32;
+			atc.output.set_sound(sound, SoundOutput::AtcBuzzer, AtsSound::PlayLooping as i32);
 		}
 	}
+	atc.output.set_sound(sound, SoundOutput::ChangeoverBuzzer, AtsSound::Stop as i32);
 	match atc.atc_brake_status {
 		AtcBrakeStatus::EmergencyBraking => get_emg_brake_handle(atc, handles),
 		AtcBrakeStatus::HalfBraking(_) => get_half_brake_handle(atc, handles),
@@ -106,7 +108,99 @@ pub fn elapse_atc_brake<'a>(atc: &'a mut ULineATC, handles: AtsHandles, state: A
 	}
 }
 
+/// ATC開放時にElapse内のATCブレーキ判定を行う関数
+/// (ATC速度照査を行わず、開放時の制限速度のみを照査する)
+pub fn elapse_atc_release_brake<'a>(atc: &'a mut ULineATC, handles: AtsHandles, state: AtsVehicleState, sound: &'a mut [i32]) -> AtsHandles {
+	if enable_atc_brake(atc.settings.atc_release.limit_speed + atc.settings.atc.check_speed_margin, state.speed) {
+		atc.atc_brake_status = AtcBrakeStatus::FullBraking;
+	} else {
+		atc.atc_brake_status = AtcBrakeStatus::Passing;
+	}
+
+	match atc.atc_brake_status {
+		AtcBrakeStatus::Passing => {
+			atc.output.set_sound(sound, SoundOutput::AtcBuzzer, AtsSound::Stop as i32);
+		},
+		_ => {
+			atc.output.set_sound(sound, SoundOutput::AtcBuzzer, AtsSound::PlayLooping as i32);
+		}
+	}
+	atc.output.set_sound(sound, SoundOutput::ChangeoverBuzzer, AtsSound::Stop as i32);
+	match atc.atc_brake_status {
+		AtcBrakeStatus::FullBraking => get_full_brake_handle(atc, handles),
+		_ => get_none_brake_handle(atc, handles),
+	}
+}
+
+/// 入換時にElapse内のATCブレーキ判定を行う関数
+/// (入換信号以外を受信した場合は切換要請ブザーを鳴動させ、切り換えるまでブレーキを作動させる)
+pub fn elapse_irekae_brake<'a>(atc: &'a mut ULineATC, handles: AtsHandles, state: AtsVehicleState, sound: &'a mut [i32]) -> AtsHandles {
+	let enable_auto_brake = match atc.now_signal {
+		AtcSignal::Irekae15 => enable_atc_brake(atc.settings.irekae.signal15_speed + atc.settings.irekae.check_speed_margin, state.speed),
+		AtcSignal::Irekae25 => enable_atc_brake(atc.settings.irekae.signal25_speed + atc.settings.irekae.check_speed_margin, state.speed),
+		_ => true,
+	};
+	// 入換では緩和ブレーキを使わず、照査速度を超えると常用最大ブレーキが作動する
+	if enable_auto_brake {
+		atc.atc_brake_status = AtcBrakeStatus::FullBraking;
+	} else {
+		atc.atc_brake_status = AtcBrakeStatus::Passing;
+	}
+
+	match atc.atc_brake_status {
+		AtcBrakeStatus::Passing => {
+			atc.output.set_sound(sound, SoundOutput::AtcBuzzer, AtsSound::Stop as i32);
+		},
+		_ => {
+			atc.output.set_sound(sound, SoundOutput::AtcBuzzer, AtsSound::PlayLooping as i32);
+		}
+	}
+	// 本線の信号を受信した場合は切換要請
+	if atc.now_signal.is_irekae() {
+		atc.output.set_sound(sound, SoundOutput::ChangeoverBuzzer, AtsSound::Stop as i32);
+	} else {
+		atc.output.set_sound(sound, SoundOutput::ChangeoverBuzzer, AtsSound::PlayLooping as i32);
+	}
+	match atc.atc_brake_status {
+		AtcBrakeStatus::FullBraking => get_full_brake_handle(atc, handles),
+		_ => get_none_brake_handle(atc, handles),
+	}
+}
+
 /// ATC非設時にElapse内のATCブレーキ判定を行う関数
-pub fn elapse_hisetsu_brake(_atc: &mut ULineATC, handles: AtsHandles) -> AtsHandles {
-	handles
-}
\ No newline at end of file
+/// (02以外の信号を受信した場合は切換要請ブザーを鳴動させ、切り換えるまでブレーキを作動させる)
+pub fn elapse_hisetsu_brake<'a>(atc: &'a mut ULineATC, handles: AtsHandles, state: AtsVehicleState, sound: &'a mut [i32]) -> AtsHandles {
+	// 切換要請が発生した瞬間
+	if !atc.hisetsu_changeover_request && atc.now_signal != AtcSignal::Signal02 {
+		atc.hisetsu_changeover_request = true;
+		warn!("[HisetsuChangeoverRequest] 非設時に信号を受信しました。({:?}, {}km/h)", atc.now_signal, state.speed);
+	}
+
+	atc.output.set_sound(sound, SoundOutput::AtcBuzzer, AtsSound::Stop as i32);
+	if !atc.hisetsu_changeover_request {
+		atc.atc_brake_status = AtcBrakeStatus::Passing;
+		atc.output.set_sound(sound, SoundOutput::ChangeoverBuzzer, AtsSound::Stop as i32);
+		return handles;
+	}
+
+	// 運転切換スイッチを切り換えるまでブレーキを作動させる
+	atc.output.set_sound(sound, SoundOutput::ChangeoverBuzzer, AtsSound::PlayLooping as i32);
+	match atc.settings.hisetsu.brake {
+		HisetsuBrake::None => {
+			atc.atc_brake_status = AtcBrakeStatus::Passing;
+			get_none_brake_handle(atc, handles)
+		},
+		HisetsuBrake::Half => {
+			atc.atc_brake_status = AtcBrakeStatus::HalfBraking(state.time);
+			get_half_brake_handle(atc, handles)
+		},
+		HisetsuBrake::Full => {
+			atc.atc_brake_status = AtcBrakeStatus::FullBraking;
+			get_full_brake_handle(atc, handles)
+		},
+		HisetsuBrake::Emergency => {
+			atc.atc_brake_status = AtcBrakeStatus::EmergencyBraking;
+			get_emg_brake_handle(atc, handles)
+		},
+	}
+}
@@ -0,0 +1,33 @@
+use bveats_rs::{AtsConstantSpeed, AtsHandles, AtsVehicleState};
+use log::info;
+
+use super::{speed_control::cancel_constant_speed, uline_atc::ULineATC};
+
+/// 戸閉保安が作動しているかを判断する関数
+/// (ドアが開いている間と、閉まってから解除遅延時間が経過するまで)
+pub fn is_door_interlocked(atc: &ULineATC, time: i32) -> bool {
+	if !atc.settings.door.enable {
+		return false;
+	}
+	atc.is_door_open || atc.door_closed_time.is_some_and(|closed| time < closed + atc.settings.door.release_delay)
+}
+
+/// Elapse内で戸閉保安の判定を行う関数 (作動中は力行を0にし、設定に応じてブレーキを保持する)
+pub fn elapse_door_interlock(atc: &mut ULineATC, mut handles: AtsHandles, state: AtsVehicleState) -> AtsHandles {
+	let interlocked = is_door_interlocked(atc, state.time);
+	if !interlocked {
+		if atc.door_closed_time.take().is_some() {
+			info!("[DoorInterlock] 解除");
+		}
+		return handles;
+	}
+	if atc.is_constant_control {
+		cancel_constant_speed(atc, "戸閉保安");
+	}
+	handles.power = 0;
+	handles.constant_speed = AtsConstantSpeed::Disable as i32;
+	if atc.settings.door.brake_notch > 0 {
+		handles.brake = handles.brake.max(atc.settings.vehicle.get_output_brake_notch(atc.settings.door.brake_notch));
+	}
+	handles
+}
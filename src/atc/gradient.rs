@@ -0,0 +1,102 @@
+//! 勾配地上子で受信した線路の勾配
+//!
+//! ATO・TASC・定速制御・抑速制御のノッチに、勾配を打ち消すフィードフォワード項を加えるために使用する。
+//! 勾配は上り勾配を正とする[‰]で扱う。
+
+use crate::settings::Settings;
+
+/// 1‰の勾配による加速度 (重力加速度 9.80665m/s^2 を km/h/s に換算して1000で割った値) [km/h/s]
+const GRADIENT_ACCELERATION_PER_PERMILLE: f32 = 9.80665 * 3.6 / 1000.0;
+
+/// 勾配による加速度を取得する関数 (下り勾配で正) [km/h/s]
+pub fn get_gradient_acceleration(gradient: f32) -> f32 {
+    -gradient * GRADIENT_ACCELERATION_PER_PERMILLE
+}
+
+/// 勾配を打ち消すために加える加速度を取得する関数 (下り勾配で正, 無効の場合は0) [km/h/s]
+pub fn get_feed_forward_acceleration(settings: &Settings, gradient: f32) -> f32 {
+    if !settings.gradient.enable {
+        return 0.0;
+    }
+    get_gradient_acceleration(gradient) * settings.gradient.feed_forward_gain
+}
+
+/// 勾配を打ち消すために加える力行ノッチとブレーキノッチを取得する関数
+/// (上り勾配では力行ノッチが正・ブレーキノッチが負, 下り勾配ではその逆になる)
+pub fn get_feed_forward_notches(settings: &Settings, gradient: f32) -> (f32, f32) {
+    let acceleration = get_feed_forward_acceleration(settings, gradient);
+    let vehicle = &settings.vehicle;
+    (
+        -acceleration / settings.gradient.max_acceleration * vehicle.output_power_notches as f32,
+        acceleration / settings.tasc.max_deceleration * vehicle.output_brake_notches as f32,
+    )
+}
+
+/// 勾配を打ち消すために加える操作量を取得する関数 (正:力行 負:ブレーキ のノッチ)
+pub fn get_feed_forward_notch(settings: &Settings, gradient: f32) -> f32 {
+    let (power, brake) = get_feed_forward_notches(settings, gradient);
+    power.max(0.0) - brake.max(0.0)
+}
+
+/// 勾配地上子で受信した勾配と、予告された勾配の変化点
+#[derive(Debug, Default)]
+#[derive(Clone)]
+pub struct GradientTracker {
+    /// 現在の勾配 [‰]
+    gradient: f32,
+    /// 予告された勾配の変化点 (位置[m], 勾配[‰]) (位置の順)
+    changes: Vec<(f64, f32)>,
+}
+
+impl GradientTracker {
+    /// 現在の勾配を取得する関数 [‰]
+    pub fn get_gradient(&self) -> f32 {
+        self.gradient
+    }
+    /// 予告された勾配の変化点を取得する関数
+    pub fn get_changes(&self) -> &[(f64, f32)] {
+        &self.changes
+    }
+    /// 地上子の任意の値 (勾配[‰] * 10) から現在の勾配を設定する関数
+    pub fn set_from_beacon(&mut self, optional: i32) {
+        self.gradient = optional as f32 / 10.0;
+    }
+    /// 地上子の任意の値 (勾配[‰] * 10 * 10000 ± 変化点までの距離[m]) から勾配の変化点を追加する関数
+    /// (下り勾配は全体を負の値にする 例: -3500800 → 800m先から35‰の下り勾配)
+    pub fn add_change_from_beacon(&mut self, optional: i32, location: f64) {
+        let gradient = (optional / 10000) as f32 / 10.0;
+        let change_location = location + (optional % 10000).abs() as f64;
+        // 同じ位置以降の予告は新しい予告で置き換える
+        self.changes.retain(|(location, _)| *location < change_location);
+        self.changes.push((change_location, gradient));
+    }
+    /// 通過した変化点の勾配を現在の勾配にする関数 (Elapse毎に呼び出す)
+    pub fn elapse(&mut self, location: f64) {
+        while let Some(&(change_location, gradient)) = self.changes.first() {
+            if change_location > location {
+                break;
+            }
+            self.gradient = gradient;
+            self.changes.remove(0);
+        }
+    }
+    /// 現在位置から`distance`[m]先までの平均の勾配を取得する関数 [‰]
+    pub fn get_average_gradient(&self, location: f64, distance: f32) -> f32 {
+        if distance <= 0.0 {
+            return self.gradient;
+        }
+        let end = location + distance as f64;
+        let mut sum = 0.0;
+        let (mut start, mut gradient) = (location, self.gradient);
+        for &(change_location, change_gradient) in &self.changes {
+            if change_location >= end {
+                break;
+            }
+            let change_location = change_location.max(start);
+            sum += (change_location - start) * gradient as f64;
+            (start, gradient) = (change_location, change_gradient);
+        }
+        sum += (end - start) * gradient as f64;
+        (sum / distance as f64) as f32
+    }
+}
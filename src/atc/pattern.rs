@@ -0,0 +1,32 @@
+/// パターン式ATCの地上子で受信した目標
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub struct PatternTarget {
+    /// 目標速度 [km/h]
+    pub target_speed: f32,
+    /// 目標地点 [m]
+    pub target_location: f64,
+}
+
+impl PatternTarget {
+    /// 地上子の任意の値 (目標速度[km/h] * 10000 + 目標距離[m]) からパターンの目標を作成する関数
+    pub fn from_beacon(optional: i32, location: f64) -> Option<Self> {
+        if optional < 0 {
+            return None;
+        }
+        Some(Self {
+            target_speed: (optional / 10000) as f32,
+            target_location: location + (optional % 10000) as f64,
+        })
+    }
+
+    /// 現在位置でのパターン速度を取得する関数
+    /// * `deceleration` - パターンの減速度 [km/h/s]
+    /// * `margin_distance` - 目標地点の手前に確保する距離 [m]
+    pub fn get_pattern_speed(&self, location: f64, deceleration: f32, margin_distance: f32) -> f32 {
+        let remaining_distance = ((self.target_location - location) as f32 - margin_distance).max(0.0);
+        // v^2 = v0^2 + 2ax (速度[km/h], 減速度[km/h/s]を[m]に合わせるため3.6倍する)
+        (self.target_speed.powi(2) + 2.0 * deceleration * 3.6 * remaining_distance).sqrt()
+    }
+}
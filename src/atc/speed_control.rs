@@ -1,18 +1,56 @@
 //! 定速制御/抑速制御を制御する関数群
 
-use bveats_rs::{AtsConstantSpeed, AtsHandles, AtsVehicleState};
+use bveats_rs::{AtsConstantSpeed, AtsHandles};
 use log::info;
 
-use super::uline_atc::ULineATC;
+use super::{gradient::get_feed_forward_notch, uline_atc::ULineATC};
+
+/// 速度制御用のPID制御器の状態
+#[derive(Debug, Default)]
+#[derive(Clone, Copy)]
+pub struct SpeedController {
+	/// 偏差の積分値 [km/h・s]
+	integral: f32,
+	/// 前回の偏差 [km/h]
+	before_error: Option<f32>,
+}
+
+impl SpeedController {
+	/// 制御器の状態を初期化する関数
+	pub fn reset(&mut self) {
+		*self = Self::default();
+	}
+
+	/// 偏差から操作量(ノッチ, 正:力行 負:ブレーキ)を計算する関数
+	/// * `error` - 目標速度との偏差 [km/h]
+	/// * `delta` - 前回からの経過時間 [s]
+	/// * `gains` - (比例, 積分, 微分) ゲイン
+	/// * `min`, `max` - 操作量の範囲 (積分値もこの範囲に制限する)
+	pub fn update(&mut self, error: f32, delta: f32, gains: (f32, f32, f32), min: f32, max: f32) -> f32 {
+		let (kp, ki, kd) = gains;
+		if delta <= 0.0 {
+			return (kp * error + ki * self.integral).clamp(min, max);
+		}
+		// 積分項が操作量の範囲を超えないようにする (ワインドアップ対策)
+		if ki != 0.0 {
+			self.integral = (self.integral + error * delta).clamp(min / ki.abs(), max / ki.abs());
+		}
+		let derivative = match self.before_error {
+			Some(before_error) => (error - before_error) / delta,
+			None => 0.0,
+		};
+		self.before_error = Some(error);
+		(kp * error + ki * self.integral + kd * derivative).clamp(min, max)
+	}
+}
 
 /// 定速制御の条件を満たしているかを判断する関数
-/// (速度が15km/h以上で、P4→P3に戻された時に条件を満たす)
+/// (速度が定速制御開始速度以上で、P4→P3に戻された時に条件を満たし、その時の速度を目標速度とする)
 pub fn is_constant_speed(atc: &mut ULineATC, speed: f32, beforeNotch: i32, afterNotch: i32) -> bool {
-	info!("[CONSTANT] Target: {:?}", atc.constant_target_speed);
 	if speed >= atc.settings.vehicle.constant_start_speed && beforeNotch == 4 && afterNotch == 3 {
-		if atc.constant_target_speed == 0.0 {
-			atc.constant_target_speed = atc.speed;
-		}
+		atc.constant_target_speed = speed;
+		atc.constant_controller.reset();
+		info!("[CONSTANT] Target: {:?}", atc.constant_target_speed);
 		true
 	} else {
 		atc.constant_target_speed = 0.0;
@@ -20,10 +58,80 @@ pub fn is_constant_speed(atc: &mut ULineATC, speed: f32, beforeNotch: i32, after
 	}
 }
 
+/// 定速制御を解除する関数
+pub fn cancel_constant_speed(atc: &mut ULineATC, reason: &str) {
+	if atc.is_constant_control {
+		info!("[CONSTANT] 解除 ({reason})");
+	}
+	atc.is_constant_control = false;
+	atc.constant_target_speed = 0.0;
+	atc.constant_controller.reset();
+}
+
+/// 定速制御の操作量を更新する関数 (Elapse毎に呼び出す)
+pub fn elapse_constant_speed(atc: &mut ULineATC, speed: f32, delta: i32) {
+	if !atc.is_constant_control {
+		atc.constant_notch = 0.0;
+		return;
+	}
+	let vehicle = &atc.settings.vehicle;
+	let gains = (vehicle.constant_kp, vehicle.constant_ki, vehicle.constant_kd);
+	let min = -vehicle.output_brake_notches as f32;
+	let max = vehicle.output_power_notches as f32;
+	let error = atc.constant_target_speed - speed;
+	// 勾配を打ち消すノッチを加える (合計が操作量の範囲に収まるように制御器の範囲をずらす)
+	let feed_forward = get_feed_forward_notch(&atc.settings, atc.gradient.get_gradient());
+	atc.constant_notch = atc.constant_controller.update(error, delta as f32 / 1000.0, gains, min - feed_forward, max - feed_forward) + feed_forward;
+}
+
 /// 抑速制御の条件を満たしているかを判断する関数
-/// (速度が25km/h以上で、B2→B1に戻された時に条件を満たす)
-pub fn is_holding_speed(atc: &ULineATC, speed: f32, beforeNotch: i32, afterNotch: i32) -> bool {
-	speed >= atc.settings.vehicle.yokusoku_start_speed && beforeNotch == -2 && afterNotch == -1
+/// (速度が抑速制御開始速度以上で抑速ノッチが投入された時に条件を満たし、その時の速度を基準速度とする)
+/// (抑速ノッチ間の操作では基準速度を維持する)
+pub fn is_holding_speed(atc: &mut ULineATC, speed: f32, beforeNotch: i32, afterNotch: i32) -> bool {
+	if afterNotch >= 0 {
+		if atc.is_holding_control {
+			info!("[HOLDING] 解除");
+		}
+		atc.holding_base_speed = 0.0;
+		return false;
+	}
+	if beforeNotch < 0 && atc.is_holding_control {
+		return true;
+	}
+	if speed >= atc.settings.vehicle.yokusoku_start_speed {
+		atc.holding_base_speed = speed;
+		atc.holding_controller.reset();
+		info!("[HOLDING] Base: {:?}", atc.holding_base_speed);
+		true
+	} else {
+		false
+	}
+}
+
+/// 抑速ノッチに応じた抑速制御の目標速度を取得する関数
+pub fn get_holding_target_speed(atc: &ULineATC, notch: i32) -> f32 {
+	let offsets = &atc.settings.vehicle.holding_speed_offsets;
+	let offset = match offsets.len() {
+		0 => 0.0,
+		len => offsets[((-notch).max(1) as usize - 1).min(len - 1)],
+	};
+	(atc.holding_base_speed - offset).max(0.0)
+}
+
+/// 抑速制御の操作量を更新する関数 (Elapse毎に呼び出す)
+pub fn elapse_holding_speed(atc: &mut ULineATC, speed: f32, delta: i32) {
+	if !atc.is_holding_control {
+		atc.holding_notch = 0.0;
+		return;
+	}
+	let vehicle = &atc.settings.vehicle;
+	let gains = (vehicle.holding_kp, vehicle.holding_ki, vehicle.holding_kd);
+	// 電気ブレーキは抑速の最大ブレーキノッチまでとする
+	let max_brake = vehicle.get_holding_max_brake_notch() as f32;
+	let error = get_holding_target_speed(atc, atc.man_power) - speed;
+	// 勾配を打ち消すノッチを加える (合計が操作量の範囲に収まるように制御器の範囲をずらす)
+	let feed_forward = get_feed_forward_notch(&atc.settings, atc.gradient.get_gradient());
+	atc.holding_notch = -(atc.holding_controller.update(error, delta as f32 / 1000.0, gains, -max_brake - feed_forward, -feed_forward) + feed_forward);
 }
 
 /// 空制の抑速制御の条件を満たしているかを判断する関数
@@ -33,22 +141,28 @@ pub fn is_air_holding_speed(atc: &ULineATC, speed: f32, notch: i32) -> bool {
 }
 
 /// 定速制御を適用する関数
-fn constant_speed(_atc: &ULineATC, handles: AtsHandles) -> AtsHandles {
-    /* handles.constant_speed = AtsConstantSpeed::Enable as i32;
-	handles */
-	atc_constant_speed(_atc)
+/// (操作量に応じて力行/ブレーキノッチを出力し、レバーサは運転士の操作をそのまま使う)
+fn constant_speed(atc: &ULineATC, mut handles: AtsHandles) -> AtsHandles {
+	let notch = atc.constant_notch.round() as i32;
+	handles.power = notch.clamp(0, atc.settings.vehicle.output_power_notches);
+	handles.brake = handles.brake.max((-notch).clamp(0, atc.settings.vehicle.output_brake_notches));
+	handles.constant_speed = AtsConstantSpeed::Disable as i32;
+	handles
 }
 
 /// 抑速制御を適用する関数
-fn holding_speed(_atc: &ULineATC, mut handles: AtsHandles) -> AtsHandles {
-    handles.constant_speed = AtsConstantSpeed::Enable as i32;
+/// (目標速度を維持するように電気ブレーキのノッチを出力する)
+fn holding_speed(atc: &ULineATC, mut handles: AtsHandles) -> AtsHandles {
+	handles.brake = handles.brake.max(atc.holding_notch.round() as i32);
 	handles.power = 0;
+	handles.constant_speed = AtsConstantSpeed::Disable as i32;
 	handles
 }
 
 /// 空制の抑速制御を適用する関数
+/// (低速で電気ブレーキが効かなくなるため、抑速ノッチに応じた空気ブレーキに引き継ぐ)
 fn air_holding_speed(atc: &ULineATC, mut handles: AtsHandles) -> AtsHandles {
-    handles.brake = atc.convert_output_notch(handles.power.abs()).max(handles.brake);
+	handles.brake = atc.settings.vehicle.get_output_holding_notch(atc.man_power.abs()).max(handles.brake);
 	handles.power = 0;
 	handles.reverser = 0;
 	handles
@@ -57,7 +171,7 @@ fn air_holding_speed(atc: &ULineATC, mut handles: AtsHandles) -> AtsHandles {
 /// 定速制御/抑速制御の判定を満たした上でAtsHandlesを返す関数
 pub fn constant_and_holding_speed(atc: &ULineATC, mut handles: AtsHandles, is_constant_speed: bool, is_holding_speed: bool, is_air_holding_speed: bool) -> AtsHandles {
 	if handles.power < 0 {
-		handles.brake = atc.convert_output_notch(-handles.power);
+		handles.brake = atc.settings.vehicle.get_output_holding_notch(-handles.power);
 		handles.power = 0;
 	}
 	if is_constant_speed {
@@ -73,24 +187,3 @@ pub fn constant_and_holding_speed(atc: &ULineATC, mut handles: AtsHandles, is_co
 
 	handles
 }
-
-pub fn atc_constant_speed(atc: &ULineATC) -> AtsHandles {
-	let delta = atc.time - atc.before_time;
-	let acceleration_km_h_s = (atc.speed - atc.before_speed) / (delta as f32 / 1000.0);
-
-	let speed_2second = atc.speed + (acceleration_km_h_s * 1.0);
-	let target_speed = atc.constant_target_speed; // ATO目標速度
-	let speed_diff = target_speed as f32 - speed_2second;
-	let mut power_notch: i32 = (speed_diff / 0.4) as i32;
-	let mut brake_notch = (speed_diff / 0.3) as i32;
-
-	power_notch += ((speed_diff) / 0.50).clamp(-10.0, 10.0) as i32;
-	brake_notch += ((speed_diff) / 0.50).clamp(-10.0, 10.0) as i32;
-
-	AtsHandles {
-		power: power_notch.clamp(0, 31),
-		brake: -brake_notch.clamp(-31, 0),
-		reverser: 1,
-		constant_speed: AtsConstantSpeed::Disable as i32
-	}
-}
\ No newline at end of file
@@ -1,11 +1,12 @@
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use ::bveats_rs::*;
-use crate::{atc::{atc_signal::*, auto_brake::elapse_hisetsu_brake, speed_control::{is_constant_speed, is_holding_speed}}, ato::uline_ato::ULineATO, settings::Settings, tims::TIMS};
-use std::path::PathBuf;
-use log::{error, info, debug};
-#[cfg(windows)]
-use crate::DLL_PATH;
+use crate::{atc::{atc_signal::*, auto_brake::elapse_hisetsu_brake, pattern::PatternTarget, speed_control::{cancel_constant_speed, elapse_constant_speed, elapse_holding_speed, is_constant_speed, is_holding_speed, SpeedController}}, ato::{brake_model::{BrakeModel, BrakeModelFile}, tasc_report::{TascReport, TascReportEntry, TascStop}, uline_ato::ULineATO}, output::{OutputLayout, PanelOutput, SoundOutput}, settings::{profile::SettingsFile, KeyAction, validation::{ValidationIssue, ValidationReport}, Settings}, tims::TIMS};
+use log::{error, info, debug, warn};
+use crate::get_dll_directory;
 
-use super::{auto_brake::elapse_atc_brake, speed_control::{constant_and_holding_speed, is_air_holding_speed}};
+use super::{gradient::GradientTracker, door_interlock::{elapse_door_interlock, is_door_interlocked}, auto_brake::{elapse_atc_brake, elapse_atc_release_brake, elapse_irekae_brake}, speed_control::{constant_and_holding_speed, is_air_holding_speed}};
 
 /// panelのサイズ
 const ELAPSE_PANEL_SIZE: usize = 256;
@@ -24,6 +25,7 @@ pub enum AtcBrakeStatus {
     /// ATC非常ブレーキ中
     EmergencyBraking,
 }
+#[allow(clippy::derivable_impls)]
 impl Default for AtcBrakeStatus {
     fn default() -> Self {
         Self::Passing
@@ -44,6 +46,7 @@ pub enum AtcStatus {
     /// 非設
     Hisetsu,
 }
+#[allow(clippy::derivable_impls)]
 impl Default for AtcStatus {
     fn default() -> Self {
         Self::ATC
@@ -87,6 +90,7 @@ pub enum EmgSound {
     /// 非常ブレーキ
     EmergencyBrake,
 }
+#[allow(clippy::derivable_impls)]
 impl Default for EmgSound {
     fn default() -> Self {
         Self::None
@@ -109,12 +113,29 @@ pub enum EmgSoundKeyDown {
     /// 非常ブレーキ
     L(i32),
 }
+#[allow(clippy::derivable_impls)]
 impl Default for EmgSoundKeyDown {
     fn default() -> Self {
         Self::None
     }
 }
 
+/// ATC開放スイッチの操作記録
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+pub struct AtcReleaseRecord {
+    /// 操作した時刻 [ms]
+    pub time: i32,
+    /// 操作した位置 [m]
+    pub location: f64,
+    /// 操作した時の速度 [km/h]
+    pub speed: f32,
+    /// 操作した時の信号
+    pub signal: AtcSignal,
+    /// ATC開放したか (falseの場合は復帰)
+    pub enable: bool,
+}
+
 pub struct ULineATC {
     /// 車両諸元
     pub vehicle_spec: AtsVehicleSpec,
@@ -136,10 +157,21 @@ pub struct ULineATC {
     pub enable_02hijo_unten: bool,
     /// 確認運転が有効になっているか
     pub enable_01kakunin_unten: bool,
+    /// 現示ダウン時に新しい信号速度を超えているか (注意喚起用)
+    caution_downgrade: bool,
+    /// 注意喚起チャイムを最後に鳴動させた時刻
+    caution_time: Option<i32>,
+    /// パターン式ATCの目標
+    pub pattern_target: Option<PatternTarget>,
+    /// パターン式ATCのパターン速度 [km/h]
+    pub pattern_speed: Option<f32>,
+    /// 非設時の切換要請中か
+    pub hisetsu_changeover_request: bool,
 
     // Natives
     pub time: i32,
     pub speed: f32,
+    pub location: f64,
 
     /// TIMS
     tims: TIMS,
@@ -160,145 +192,451 @@ pub struct ULineATC {
     /// 現在抑速制御中か
     pub is_holding_control: bool,
     /// ATC開放中か
-    atc_disable: bool,
+    pub atc_disable: bool,
+    /// ATC開放スイッチの封印解除待ちを開始した時刻
+    atc_release_request_time: Option<i32>,
+    /// ATC開放スイッチの操作記録
+    pub atc_release_history: Vec<AtcReleaseRecord>,
 
     /// 戸閉保安が待機中か
     wait_door_close_security: bool,
     /// 戸閉時刻
     door_close_time: i32,
+    /// ドアが開いているか
+    pub is_door_open: bool,
+    /// 戸閉保安の解除待ちの間のドアが閉まった時刻 [ms]
+    pub door_closed_time: Option<i32>,
 
     /// ATO
-    ato: ULineATO,
+    pub ato: ULineATO,
+    /// TASCの停止精度のレポート
+    pub tasc_report: TascReport,
+    /// 勾配地上子で受信した勾配
+    pub gradient: GradientTracker,
 
     pub before_time: i32,
     pub before_speed: f32,
     pub before_acceleration: f32,
 
     pub constant_target_speed: f32,
+    /// 定速制御の制御器
+    pub constant_controller: SpeedController,
+    /// 定速制御の操作量 (正:力行 負:ブレーキ) [ノッチ]
+    pub constant_notch: f32,
+    /// 抑速制御の基準速度 (抑速投入時の速度) [km/h]
+    pub holding_base_speed: f32,
+    /// 抑速制御の制御器
+    pub holding_controller: SpeedController,
+    /// 抑速制御の操作量 (ブレーキ) [ノッチ]
+    pub holding_notch: f32,
 
     pub settings: Settings,
+    /// 設定ファイルのパス (Load前に指定しない場合はDLLと同じフォルダの uline.toml)
+    pub settings_path: Option<PathBuf>,
+    /// 読み込んだ設定ファイルの更新日時
+    pub settings_modified: Option<SystemTime>,
+    /// 設定ファイルの読み込み時のエラー
+    settings_load_error: Option<String>,
+    /// 読み込んだ設定ファイル (プロファイルの切り替えに使用する)
+    settings_file: SettingsFile,
+    /// 選択中の車両プロファイル名 (Noneの場合は基本の設定)
+    pub profile_name: Option<String>,
+    /// 設定に問題があるか (TIMSの異常表示)
+    pub settings_error: bool,
+    /// パネル・サウンドの出力先
+    pub output: OutputLayout,
 }
 
 impl ULineATC {
     fn elapse_display(&mut self, _state: AtsVehicleState, _handles: &AtsHandles) {
-        for i in 31..=38 { self.tims_panel[i] = 0; }
-        match self.now_signal {
-            AtcSignal::Signal02 => self.tims_panel[31] = 1,
-            AtcSignal::Signal01 => self.tims_panel[32] = 1,
-            AtcSignal::Signal15 => self.tims_panel[33] = 1,
-            AtcSignal::Signal25 => self.tims_panel[34] = 1,
-            AtcSignal::Signal45 => self.tims_panel[35] = 1,
-            AtcSignal::Signal60 => self.tims_panel[36] = 1,
-            AtcSignal::Signal75 => self.tims_panel[37] = 1,
-            AtcSignal::Signal90 => self.tims_panel[38] = 1,
-            _ => {}
-        }
-        if self.atc_status == AtcStatus::Irekae || self.atc_status == AtcStatus::Hisetsu {
-            for i in 31..=38 {
-                self.tims_panel[i] = 0;
-            }
+        // 入換・非設時はATC信号を表示しない
+        let is_signal_shown = !(self.atc_status == AtcStatus::Irekae || self.atc_status == AtcStatus::Hisetsu);
+        // パターン速度
+        let pattern_speed = match self.pattern_speed {
+            Some(_) if self.atc_status == AtcStatus::ATC || self.atc_status == AtcStatus::ATO => Some(self.get_limit_speed()),
+            _ => None,
+        };
+        // ATC開放表示灯は点滅させる
+        let blink_interval = self.settings.atc_release.blink_interval.max(1);
+        let door_interlocked = is_door_interlocked(self, _state.time);
+
+        let output = &self.output;
+        let panel = self.tims_panel.as_mut_slice();
+        for (signal_output, signal) in [
+            (PanelOutput::Signal02, AtcSignal::Signal02), (PanelOutput::Signal01, AtcSignal::Signal01),
+            (PanelOutput::Signal15, AtcSignal::Signal15), (PanelOutput::Signal25, AtcSignal::Signal25),
+            (PanelOutput::Signal45, AtcSignal::Signal45), (PanelOutput::Signal60, AtcSignal::Signal60),
+            (PanelOutput::Signal75, AtcSignal::Signal75), (PanelOutput::Signal90, AtcSignal::Signal90),
+        ] {
+            output.set_panel(panel, signal_output, (is_signal_shown && self.now_signal == signal) as i32);
         }
-        self.tims_panel[9] = (self.man_power+3).min(7);
-        self.tims_panel[10] = (self.man_brake).min(8);
+        // 入換信号は入換時のみ表示
+        output.set_panel(panel, PanelOutput::Irekae15, (self.atc_status == AtcStatus::Irekae && self.now_signal == AtcSignal::Irekae15) as i32);
+        output.set_panel(panel, PanelOutput::Irekae25, (self.atc_status == AtcStatus::Irekae && self.now_signal == AtcSignal::Irekae25) as i32);
+        output.set_panel(panel, PanelOutput::PowerNotch, (self.man_power+3).min(7));
+        output.set_panel(panel, PanelOutput::BrakeNotch, (self.man_brake).min(8));
         /* for i in 0..8 {
             self.tims_panel[11+i] = POWER_PATTERN[((self.man_power as usize)+3).min(7)][i];
         }
         for i in 0..9 {
             self.tims_panel[21+i] = BRAKE_PATTERN[((self.man_brake as usize)).min(8)][i];
         } */
-        self.tims_panel[40] = if self.enable_02hijo_unten { 1 } else { 0 };
-        self.tims_panel[41] = if self.enable_01kakunin_unten { 1 } else { 0 };
-        self.tims_panel[19] = self.is_constant_control as i32;
-        self.tims_panel[20] = self.is_holding_control as i32;
-        self.tims_panel[46] = self.atc_disable as i32;
+        output.set_panel(panel, PanelOutput::HijoUnten, self.enable_02hijo_unten as i32);
+        output.set_panel(panel, PanelOutput::KakuninUnten, self.enable_01kakunin_unten as i32);
+        output.set_panel(panel, PanelOutput::PatternSpeed, pattern_speed.map_or(0, |speed| (speed * 10.0) as i32));
+        output.set_panel(panel, PanelOutput::Pattern, pattern_speed.is_some() as i32);
+        output.set_panel(panel, PanelOutput::ConstantSpeed, self.is_constant_control as i32);
+        output.set_panel(panel, PanelOutput::HoldingSpeed, self.is_holding_control as i32);
+        output.set_panel(panel, PanelOutput::SettingsError, self.settings_error as i32);
+        output.set_panel(panel, PanelOutput::AtcRelease, (self.atc_disable && (_state.time / blink_interval) % 2 == 0) as i32);
+        output.set_panel(panel, PanelOutput::DoorInterlock, door_interlocked as i32);
     }
     fn elapse_emg_sound(&mut self, sound: &mut [i32]) {
-        for i in 101..=105 { sound[i] = AtsSound::Continue as i32; }
-        match self.emg_sound {
-            EmgSound::SignalWait => sound[101] = AtsSound::Play as i32,
-            EmgSound::EmergencyCase => sound[102] = AtsSound::Play as i32,
-            EmgSound::EmergencyStop => sound[103] = AtsSound::Play as i32,
-            EmgSound::SeatExchange => sound[104] = AtsSound::Play as i32,
-            EmgSound::EmergencyBrake => sound[105] = AtsSound::Play as i32,
-            _ => {}
+        for (emg_output, emg_sound) in [
+            (SoundOutput::EmgSignalWait, EmgSound::SignalWait),
+            (SoundOutput::EmgEmergencyCase, EmgSound::EmergencyCase),
+            (SoundOutput::EmgEmergencyStop, EmgSound::EmergencyStop),
+            (SoundOutput::EmgSeatExchange, EmgSound::SeatExchange),
+            (SoundOutput::EmgEmergencyBrake, EmgSound::EmergencyBrake),
+        ] {
+            let value = if self.emg_sound == emg_sound { AtsSound::Play } else { AtsSound::Continue };
+            self.output.set_sound(sound, emg_output, value as i32);
         }
         if self.is_emg_brake_sound {
-            sound[106] = AtsSound::Play as i32;
+            self.output.set_sound(sound, SoundOutput::EmgBrake, AtsSound::Play as i32);
             self.is_emg_brake_sound = false;
         } else {
-            sound[106] = AtsSound::Continue as i32;
+            self.output.set_sound(sound, SoundOutput::EmgBrake, AtsSound::Continue as i32);
+        }
+    }
+    /// ATC注意喚起チャイムの処理
+    fn elapse_caution_chime(&mut self, state: AtsVehicleState, sound: &mut [i32]) {
+        let limit_speed = self.get_limit_speed();
+        if state.speed <= limit_speed {
+            self.caution_downgrade = false;
+        }
+        let is_supervised = (self.atc_status == AtcStatus::ATC || self.atc_status == AtcStatus::ATO) && !self.atc_disable;
+        // 照査速度に接近した場合 (ATCブレーキ作動後に新たに鳴動させることはしない)
+        let is_approach = self.settings.caution.approach
+            && (self.atc_brake_status == AtcBrakeStatus::Passing || self.caution_time.is_some())
+            && limit_speed > 0.0
+            && state.speed >= limit_speed + self.settings.atc.check_speed_margin - self.settings.caution.approach_speed;
+        let is_downgrade = self.settings.caution.downgrade && self.caution_downgrade;
+
+        self.output.set_sound(sound, SoundOutput::CautionChime, AtsSound::Continue as i32);
+        if !is_supervised || !(is_approach || is_downgrade) {
+            self.caution_time = None;
+            return;
+        }
+        match self.caution_time {
+            None => {
+                info!("[ATCCaution] {:?} {}km/h (接近: {is_approach}, 現示ダウン: {is_downgrade})", self.now_signal, state.speed);
+                self.output.set_sound(sound, SoundOutput::CautionChime, AtsSound::Play as i32);
+                self.caution_time = Some(state.time);
+            }
+            // ブレーキを操作している間は応答したとみなす
+            Some(_) if self.man_brake > 0 => {
+                self.caution_time = Some(state.time);
+            }
+            Some(time) => {
+                let timeout = self.settings.caution.response_timeout;
+                if timeout > 0 && time + timeout <= state.time {
+                    info!("[ATCCaution] 運転士の応答がありません。");
+                    self.output.set_sound(sound, SoundOutput::CautionChime, AtsSound::Play as i32);
+                    self.caution_time = Some(state.time);
+                }
+            }
         }
     }
     fn show_atc_status(&mut self, _panel: &mut [i32]) {
-        for i in 42..=45 { self.tims_panel[i] = 0; }
-        match self.atc_status {
-            AtcStatus::Hisetsu => self.tims_panel[42] = 1,
-            AtcStatus::Irekae => self.tims_panel[43] = 1,
-            AtcStatus::ATC => self.tims_panel[44] = 1,
-            AtcStatus::ATO => self.tims_panel[45] = 1,
+        for (status_output, status) in [
+            (PanelOutput::Hisetsu, AtcStatus::Hisetsu),
+            (PanelOutput::Irekae, AtcStatus::Irekae),
+            (PanelOutput::Atc, AtcStatus::ATC),
+            (PanelOutput::Ato, AtcStatus::ATO),
+        ] {
+            self.output.set_panel(self.tims_panel.as_mut_slice(), status_output, (self.atc_status == status) as i32);
         }
     }
 
-    #[cfg(windows)]
-    fn get_dll_directory() -> Option<PathBuf> {
-        (*DLL_PATH).clone()
+    /// ATC開放スイッチを操作した時の処理
+    fn press_atc_release_switch(&mut self) {
+        if self.atc_disable {
+            self.set_atc_release(false);
+        } else if self.settings.atc_release.seal {
+            self.atc_release_request_time = Some(self.time);
+            info!("[ATCRelease] 封印解除の確認待ち");
+        } else {
+            self.set_atc_release(true);
+        }
+    }
+    /// ATC開放スイッチの封印解除を確認した時の処理
+    fn confirm_atc_release_switch(&mut self) {
+        if let Some(time) = self.atc_release_request_time.take() {
+            if self.time - time <= self.settings.atc_release.confirm_time {
+                self.set_atc_release(true);
+            } else {
+                info!("[ATCRelease] 封印解除の確認時間を過ぎました");
+            }
+        }
+    }
+    /// ATC開放状態を変更して記録する
+    fn set_atc_release(&mut self, enable: bool) {
+        self.atc_disable = enable;
+        self.atc_release_request_time = None;
+        let record = AtcReleaseRecord {
+            time: self.time,
+            location: self.location,
+            speed: self.speed,
+            signal: self.now_signal,
+            enable,
+        };
+        warn!("[ATCRelease] {:?}", record);
+        self.atc_release_history.push(record);
+    }
+
+    /// ATCの照査速度を取得する (パターン式ATCが有効な場合はパターン速度も考慮する)
+    pub fn get_limit_speed(&self) -> f32 {
+        let signal_speed = self.now_signal.getSpeed() as f32;
+        match self.pattern_speed {
+            Some(pattern_speed) => signal_speed.min(pattern_speed),
+            None => signal_speed,
+        }
     }
 
-    fn get_settings_data(&self) -> Settings {
-        #[cfg(windows)]
-        let dll_directory = match Self::get_dll_directory() {
-            Some(dir) => dir,
+    fn get_settings_path(&self) -> Option<PathBuf> {
+        match get_dll_directory() {
+            Some(dir) => Some(dir.join("uline.toml")),
             None => {
                 error!("get_dll_directory() に 失敗しました。");
-                return Default::default();
+                None
             },
+        }
+    }
+    /// 設定ファイルの更新日時を取得する
+    fn get_settings_modified(path: &Path) -> Option<SystemTime> {
+        std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+    }
+    /// 設定をATC, ATO, TIMSに反映する
+    pub fn apply_settings(&mut self, settings: Settings) {
+        self.output = OutputLayout::new(&settings.output);
+        self.tims.output = self.output.clone();
+        self.ato.settings = settings.clone();
+        self.settings = settings;
+    }
+    /// 設定値を検証し、問題があればレポートを出力してTIMSに異常を表示する
+    fn check_settings(&mut self) {
+        let mut report = self.settings.validate_report(Some(&self.vehicle_spec));
+        if let Some(err) = &self.settings_load_error {
+            report.issues.insert(0, ValidationIssue { section: "file".to_string(), message: err.clone() });
+        }
+        self.settings_error = !report.is_ok();
+        if report.is_ok() {
+            return;
+        }
+        for issue in &report.issues {
+            error!("[Settings] [{}] {}", issue.section, issue.message);
+        }
+        self.write_settings_report(&report);
+    }
+    /// 設定値の検証レポートを設定ファイルと同じフォルダに書き込む
+    fn write_settings_report(&self, report: &ValidationReport) {
+        let Some(path) = &self.settings_path else {
+            return;
+        };
+        let report_path = path.with_file_name("uline_settings_report.txt");
+        match std::fs::write(&report_path, report.to_text()) {
+            Ok(_) => info!("[Settings] 検証レポートを出力しました。({:?})", report_path),
+            Err(err) => error!("[Settings] 検証レポートの出力に失敗しました。({:?}) {err}", report_path),
+        }
+    }
+    /// TASCの停車結果をTIMSに表示し、レポートに追加する
+    fn record_tasc_stop(&mut self, mut stop: TascStop) {
+        // TASC停車駅の地上子がない場合はTIMSの次の停車駅を記録する
+        if stop.station.is_none() {
+            stop.station = self.tims.get_next_station();
+        }
+        let (start_station, destination) = self.tims.get_stations();
+        self.tims.tasc_stop = Some(stop.clone());
+        self.tasc_report.push(TascReportEntry { stop, start_station, destination });
+        if !self.settings.tasc.report {
+            return;
+        }
+        let Some(report_path) = &self.tasc_report.path else {
+            return;
+        };
+        if let Err(err) = std::fs::write(report_path, self.tasc_report.to_text()) {
+            error!("[TASC] 停止精度レポートの出力に失敗しました。({:?}) {err}", report_path);
+        }
+    }
+    /// 学習したブレーキモデルを保存するファイルのパス
+    fn get_brake_model_path(&self) -> Option<PathBuf> {
+        self.settings_path.as_ref().map(|path| path.with_file_name("uline_brake_model.toml"))
+    }
+    /// 選択中のプロファイルの学習したブレーキモデルを読み込む
+    fn load_brake_model(&mut self) {
+        let brake_notches = self.settings.vehicle.output_brake_notches;
+        let file = match self.get_brake_model_path().map(|path| BrakeModelFile::load(&path)) {
+            Some(Ok(file)) => file,
+            Some(Err(err)) => {
+                error!("[TASC] {err}");
+                Default::default()
+            },
+            None => Default::default(),
+        };
+        self.ato.brake_model = match file.get_model(self.profile_name.as_deref(), brake_notches) {
+            Some(model) => {
+                info!("[TASC] 学習したブレーキモデルを読み込みました。(プロファイル: {:?}) {:?}", self.profile_name, model.deceleration);
+                model.clone()
+            },
+            None => BrakeModel::new(brake_notches, self.settings.tasc.max_deceleration),
         };
-        #[cfg(not(windows))]
-        let dll_directory = PathBuf::new();
-
-        let config_path = dll_directory.join("uline.toml");
-        let config_data = match std::fs::read_to_string(&config_path) {
-            Ok(data) => data,
-            Err(_) => {
-                error!("設定ファイルの読み込みに失敗しました。({:?})", config_path);
-                return Default::default();
+    }
+    /// 選択中のプロファイルの学習したブレーキモデルを保存する
+    pub fn save_brake_model(&self) {
+        if !self.settings.tasc.adaptive_brake {
+            return;
+        }
+        let Some(path) = self.get_brake_model_path() else {
+            return;
+        };
+        // 他のプロファイルの学習結果は維持する (読み込めないファイルは上書きしない)
+        let mut file = match BrakeModelFile::load(&path) {
+            Ok(file) => file,
+            Err(err) => {
+                error!("[TASC] {err} ブレーキモデルを保存しませんでした。");
+                return;
+            }
+        };
+        file.set_model(self.profile_name.as_deref(), self.ato.brake_model.clone());
+        match file.save(&path) {
+            Ok(_) => info!("[TASC] 学習したブレーキモデルを保存しました。(プロファイル: {:?}) {:?}", self.profile_name, self.ato.brake_model.deceleration),
+            Err(err) => error!("[TASC] {err}"),
+        }
+    }
+    /// 車両プロファイルを切り替える関数 (Noneの場合は基本の設定)
+    pub fn select_profile(&mut self, name: Option<String>) -> bool {
+        if name == self.profile_name {
+            return true;
+        }
+        match self.settings_file.get_settings(name.as_deref()) {
+            Ok(settings) => {
+                info!("[Profile] 車両プロファイルを切り替えました。({:?} → {:?})", self.profile_name, name);
+                self.save_brake_model();
+                self.apply_settings(settings);
+                self.profile_name = name;
+                self.load_brake_model();
+                true
+            },
+            Err(err) => {
+                error!("[Profile] 車両プロファイルを切り替えできませんでした。 {err}");
+                false
             },
+        }
+    }
+    /// 設定ファイルを再読み込みする関数 (読み込みや検証に失敗した場合は現在の設定を維持する)
+    pub fn reload_settings(&mut self) -> bool {
+        let Some(path) = self.settings_path.clone() else {
+            return false;
         };
-        let settings: Settings = match toml::from_str(&config_data) {
-            Ok(config) => config,
+        // 車両諸元を受け取る前は諸元との整合性を確認しない
+        let spec = (self.vehicle_spec.brake_notches > 0).then_some(self.vehicle_spec);
+        let loaded = SettingsFile::load(&path).and_then(|file| {
+            // 選択中のプロファイルが残っていれば維持し、なければ選択し直す
+            let profile_name = match &self.profile_name {
+                Some(name) if file.get_profile(name).is_some() => Some(name.clone()),
+                _ => match &spec {
+                    Some(spec) => file.select_by_spec(spec).map(|profile| profile.name.clone()),
+                    None => file.default_profile.clone(),
+                },
+            };
+            let settings = file.get_settings(profile_name.as_deref())?;
+            settings.validate(spec.as_ref())?;
+            Ok((file, profile_name, settings))
+        });
+        let (file, profile_name, settings) = match loaded {
+            Ok(loaded) => loaded,
             Err(err) => {
-                error!("設定ファイルのパースに失敗しました。 {err}");
-                return Default::default();
+                error!("[Reload] 設定ファイルを再読み込みできませんでした。現在の設定を維持します。 {err}");
+                let mut report = ValidationReport::default();
+                report.push("reload", err);
+                self.write_settings_report(&report);
+                return false;
             },
         };
-        settings
+        debug!("{:?}", &settings);
+        self.save_brake_model();
+        self.apply_settings(settings);
+        self.settings_file = file;
+        self.profile_name = profile_name;
+        self.load_brake_model();
+        self.settings_load_error = None;
+        self.settings_error = false;
+        self.settings_modified = Self::get_settings_modified(&path);
+        info!("[Reload] 設定ファイルを再読み込みしました。({:?}, プロファイル: {:?})", path, self.profile_name);
+        true
+    }
+
+    /// 緊急放送のキーが押された時の処理 (1秒長押しで放送する)
+    fn press_emg_sound_key(&mut self, keydown: fn(i32) -> EmgSoundKeyDown, sound: EmgSound) {
+        let pressed = keydown(self.time);
+        match self.emg_sound_keydown {
+            EmgSoundKeyDown::H(time) | EmgSoundKeyDown::I(time) | EmgSoundKeyDown::J(time) | EmgSoundKeyDown::K(time) | EmgSoundKeyDown::L(time)
+                if std::mem::discriminant(&self.emg_sound_keydown) == std::mem::discriminant(&pressed) => {
+                if (self.time - time) > 1000 {
+                    self.emg_sound = sound;
+                }
+            }
+            _ => self.emg_sound_keydown = pressed,
+        }
     }
 
+    /// 入力されたブレーキノッチを出力するブレーキノッチに変換する関数
     pub fn convert_output_notch(&self, notch: i32) -> i32 {
-        let input = self.settings.vehicle.input_brake_notches as f32;
-        let output = self.settings.vehicle.output_brake_notches as f32;
-        (notch as f32 * output / input) as i32
+        self.settings.vehicle.get_output_brake_notch(notch)
     }
     
 }
 
 impl BveAts for ULineATC {
-
     fn load(&mut self) {
-        colog::init();
+        let _ = colog::default_builder().try_init();
         info!("called Load()");
 
-        let settings = self.get_settings_data();
+        if self.settings_path.is_none() {
+            self.settings_path = self.get_settings_path();
+        }
+        self.settings_file = match &self.settings_path {
+            Some(path) if path.exists() => match SettingsFile::load(path) {
+                Ok(file) => file,
+                Err(err) => {
+                    error!("{err} デフォルト値を使用します。");
+                    self.settings_load_error = Some(err);
+                    Default::default()
+                },
+            },
+            Some(path) => {
+                info!("設定ファイルがないため、デフォルト値を使用します。({:?})", path);
+                Default::default()
+            },
+            None => Default::default(),
+        };
+        self.profile_name = self.settings_file.default_profile.clone();
+        let settings = self.settings_file.get_settings(self.profile_name.as_deref()).unwrap_or_default();
         debug!("{:?}", &settings); 
-        self.settings = settings;
-
-        self.ato.settings = self.settings.clone();
+        self.settings_modified = self.settings_path.as_deref().and_then(Self::get_settings_modified);
+        self.apply_settings(settings);
+        // TASCの停止精度のレポートはセッションごとに作成する
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs());
+        self.tasc_report.path = self.settings_path.as_ref().map(|path| path.with_file_name(format!("uline_tasc_report_{now}.txt")));
+        self.load_brake_model();
 
         self.tims.load();
         self.ato.load();
     }
     fn dispose(&mut self) {
         info!("called Dispose()");
+        self.save_brake_model();
         self.tims.dispose();
         self.ato.dispose();
     }
@@ -309,11 +647,21 @@ impl BveAts for ULineATC {
     fn set_vehicle_spec(&mut self, spec: AtsVehicleSpec) {
         info!("called SetVehicleSpec( {spec:?} )");
         self.vehicle_spec = spec;
+        let profile_name = self.settings_file.select_by_spec(&spec).map(|profile| profile.name.clone());
+        self.select_profile(profile_name);
+        self.check_settings();
         self.tims.set_vehicle_spec(spec);
         self.ato.set_vehicle_spec(spec);
     }
     fn initialize(&mut self, handle: AtsInit) {
         info!("called Initialize( {handle:?} )");
+        // 設定ファイルが更新されていれば再読み込みする
+        if self.settings.reload.on_initialize {
+            let modified = self.settings_path.as_deref().and_then(Self::get_settings_modified);
+            if modified.is_some() && modified != self.settings_modified {
+                self.reload_settings();
+            }
+        }
         self.tims.initialize(handle);
         self.ato.initialize(handle);
     }
@@ -326,13 +674,25 @@ impl BveAts for ULineATC {
         }
         self.time = state.time;
         self.speed = state.speed;
+        self.location = state.location;
+        self.gradient.elapse(state.location);
+        self.pattern_speed = match self.pattern_target {
+            Some(target) if self.settings.pattern.enable => Some(target.get_pattern_speed(
+                state.location,
+                self.settings.pattern.deceleration,
+                self.settings.pattern.margin_distance)),
+            _ => None,
+        };
         let delta = state.time - self.before_time;
         let acceleration_km_h_s = (state.speed - self.before_speed) / (delta as f32 / 1000.0);
         self.show_atc_status(panel);
         self.elapse_emg_sound(sound);
+        elapse_constant_speed(self, state.speed, delta);
+        elapse_holding_speed(self, state.speed, delta);
 
         // デフォルトのAtsHandles
         let default_handles = if self.atc_status == AtcStatus::ATO {
+            self.ato.gradient = self.gradient.clone();
             let handle = self.ato.elapse(state, panel, sound);
             AtsHandles {
                 brake: handle.brake.max(self.convert_output_notch(self.man_brake)).clamp(0, self.settings.vehicle.output_brake_notches),
@@ -341,22 +701,21 @@ impl BveAts for ULineATC {
                 constant_speed: if self.man_brake != 0 { AtsConstantSpeed::Disable as i32 } else { handle.constant_speed }
             }
         } else {
-            let input_brake = self.settings.vehicle.input_brake_notches;
-            let input_power = self.settings.vehicle.input_power_notches;
-            let output_brake = self.settings.vehicle.output_brake_notches;
-            let output_power = self.settings.vehicle.output_power_notches;
-
-            let brake = (self.man_brake * output_brake / input_brake).clamp(0, output_brake + 1);
-            let power = (self.man_power * output_power / input_power).clamp(0, output_power);
-            let yokusoku = (-self.man_power * output_brake / input_brake).clamp(0, output_brake / 2);
+            let brake = self.settings.vehicle.get_output_brake_notch(self.man_brake);
+            let power = self.settings.vehicle.get_output_power_notch(self.man_power);
+            let yokusoku = self.settings.vehicle.get_output_holding_notch(-self.man_power);
 
             AtsHandles {
-                brake: brake.max(yokusoku),
+                // 抑速制御中は抑速ノッチに応じた固定のブレーキを使わない
+                brake: if self.is_holding_control { brake } else { brake.max(yokusoku) },
                 power,
                 reverser: self.man_reverser,
                 constant_speed: AtsConstantSpeed::Continue as i32
             }
         };
+        if let Some(stop) = self.ato.tasc_stop.take() {
+            self.record_tasc_stop(stop);
+        }
         // TIMS表示用のAtsHandles (擬似空制抑速を適用しない)
         let display_handles = constant_and_holding_speed(
             self,
@@ -377,24 +736,43 @@ impl BveAts for ULineATC {
         };
 
         let display_handles = match self.atc_status {
+            AtcStatus::ATO | AtcStatus::ATC if self.atc_disable => elapse_atc_release_brake(self, display_handles, state, sound),
             AtcStatus::ATO => elapse_atc_brake(self, display_handles, state, sound),
             AtcStatus::ATC => elapse_atc_brake(self, display_handles, state, sound),
-            AtcStatus::Irekae => elapse_atc_brake(self, display_handles, state, sound),
-            AtcStatus::Hisetsu => elapse_hisetsu_brake(self, display_handles)
+            AtcStatus::Irekae => elapse_irekae_brake(self, display_handles, state, sound),
+            AtcStatus::Hisetsu => elapse_hisetsu_brake(self, display_handles, state, sound)
         };
-        let mut control_handles = match self.atc_status {
+        let control_handles = match self.atc_status {
+            AtcStatus::ATO | AtcStatus::ATC if self.atc_disable => elapse_atc_release_brake(self, control_handles, state, sound),
             AtcStatus::ATO => elapse_atc_brake(self, control_handles, state, sound),
             AtcStatus::ATC => elapse_atc_brake(self, control_handles, state, sound),
-            AtcStatus::Irekae => elapse_atc_brake(self, control_handles, state, sound),
-            AtcStatus::Hisetsu => elapse_hisetsu_brake(self, control_handles)
+            AtcStatus::Irekae => elapse_irekae_brake(self, control_handles, state, sound),
+            AtcStatus::Hisetsu => elapse_hisetsu_brake(self, control_handles, state, sound)
         };
 
+        // 戸閉保安
+        let mut control_handles = elapse_door_interlock(self, control_handles, state);
+
+        // ATO運転中はブレーキノッチごとの減速度を学習する (ATO以外では出力ノッチの整形を初期状態に戻す)
+        if self.atc_status == AtcStatus::ATO {
+            self.ato.observe_brake(state, control_handles);
+        } else {
+            self.ato.brake_model.reset_observation();
+            self.ato.output_shaper.reset();
+        }
+
+        // ATCブレーキが作動した場合は定速制御を解除する
+        if self.is_constant_control && self.atc_brake_status != AtcBrakeStatus::Passing {
+            cancel_constant_speed(self, "ATCブレーキ");
+        }
+
         // ATC音関連
+        self.elapse_caution_chime(state, sound);
         if self.is_changing_signal {
-            sound[2] = AtsSound::Play as i32;
+            self.output.set_sound(sound, SoundOutput::AtcBell, AtsSound::Play as i32);
             self.is_changing_signal = false;
         } else {
-            sound[2] = AtsSound::Continue as i32;
+            self.output.set_sound(sound, SoundOutput::AtcBell, AtsSound::Continue as i32);
         }
 
         if self.wait_door_close_security && self.door_close_time == 0 {
@@ -403,9 +781,9 @@ impl BveAts for ULineATC {
         if self.wait_door_close_security && self.door_close_time + 10000 < state.time {
             self.wait_door_close_security = false;
             self.door_close_time = 0;
-            sound[20] = AtsSound::Play as i32;
+            self.output.set_sound(sound, SoundOutput::DoorCloseRelease, AtsSound::Play as i32);
         } else {
-            sound[20] = AtsSound::Continue as i32;
+            self.output.set_sound(sound, SoundOutput::DoorCloseRelease, AtsSound::Continue as i32);
         }
 
         self.elapse_display(state, &display_handles);
@@ -443,6 +821,9 @@ impl BveAts for ULineATC {
     fn set_brake(&mut self, notch: i32) {
         info!("called SetBrake( {notch} )");
         self.man_brake = notch;
+        if notch > 0 {
+            cancel_constant_speed(self, "ブレーキ操作");
+        }
         if notch == self.vehicle_spec.brake_notches + 1 && self.speed > 5.0 {
             self.is_emg_brake_sound = true;
         }
@@ -452,116 +833,62 @@ impl BveAts for ULineATC {
     }
     fn set_reverser(&mut self, notch: i32) {
         info!("called SetReverser( {notch} )");
+        if notch != self.man_reverser {
+            cancel_constant_speed(self, "レバーサ操作");
+        }
         self.man_reverser = notch;
         self.tims.set_reverser(notch);
         self.ato.set_brake(notch);
     }
     fn key_down(&mut self, key: AtsKey) {
         info!("called KeyDown( {key:?} )");
-        match key {
-            AtsKey::D => { // 2 非常運転
+        match self.settings.get_key_action(key) {
+            KeyAction::AtcRelease => self.press_atc_release_switch(),
+            KeyAction::AtcReleaseConfirm => self.confirm_atc_release_switch(),
+            KeyAction::ReloadSettings => { self.reload_settings(); },
+            KeyAction::HijoUnten => { // 非常運転
                 self.enable_01kakunin_unten = false;
                 self.enable_02hijo_unten = true;
             }
-            AtsKey::E => { // 3 確認運転
+            KeyAction::KakuninUnten => { // 確認運転
                 self.enable_01kakunin_unten = true;
                 self.enable_02hijo_unten = false;
             }
-            AtsKey::C1 => { // PageUp 運転切換スイッチ左
-                self.atc_status = self.atc_status.get_left_status();
-                info!("[ATCStatusChange] {:?}", self.atc_status);
-            }
-            AtsKey::C2 => { // PageDown 運転切換スイッチ右
-                self.atc_status = self.atc_status.get_right_status();
-                info!("[ATCStatusChange] {:?}", self.atc_status);
-            }
-            AtsKey::H => { // 6 非常放送 信号待ち
-                if let EmgSoundKeyDown::None = self.emg_sound_keydown {
-                    self.emg_sound_keydown = EmgSoundKeyDown::H(self.time);
-                }
-                match self.emg_sound_keydown {
-                    EmgSoundKeyDown::H(time) => {
-                        if (self.time - time) > 1000 {
-                            self.emg_sound = EmgSound::SignalWait;
-                        }
-                    }
-                    _ => {
-                        self.emg_sound_keydown = EmgSoundKeyDown::H(self.time);
-                    }
-                }
-            }
-            AtsKey::I => { // 7 非常放送 急病人対応
-                if let EmgSoundKeyDown::None = self.emg_sound_keydown {
-                    self.emg_sound_keydown = EmgSoundKeyDown::I(self.time);
-                }
-                match self.emg_sound_keydown {
-                    EmgSoundKeyDown::I(time) => {
-                        if (self.time - time) > 1000 {
-                            self.emg_sound = EmgSound::EmergencyCase;
-                        }
-                    }
-                    _ => {
-                        self.emg_sound_keydown = EmgSoundKeyDown::I(self.time);
-                    }
-                }
-            }
-            AtsKey::J => { // 8 非常放送 緊急停止
-                if let EmgSoundKeyDown::None = self.emg_sound_keydown {
-                    self.emg_sound_keydown = EmgSoundKeyDown::J(self.time);
-                }
-                match self.emg_sound_keydown {
-                    EmgSoundKeyDown::J(time) => {
-                        if (self.time - time) > 1000 {
-                            self.emg_sound = EmgSound::EmergencyStop;
-                        }
-                    }
-                    _ => {
-                        self.emg_sound_keydown = EmgSoundKeyDown::J(self.time);
-                    }
+            KeyAction::ModeLeft => { // 運転切換スイッチ左
+                let status = self.atc_status.get_left_status();
+                if status != self.atc_status {
+                    self.hisetsu_changeover_request = false;
                 }
+                self.atc_status = status;
+                info!("[ATCStatusChange] {:?}", self.atc_status);
             }
-            AtsKey::K => { // 9 非常放送 シート交換
-                if let EmgSoundKeyDown::None = self.emg_sound_keydown {
-                    self.emg_sound_keydown = EmgSoundKeyDown::K(self.time);
-                }
-                match self.emg_sound_keydown {
-                    EmgSoundKeyDown::K(time) => {
-                        if (self.time - time) > 1000 {
-                            self.emg_sound = EmgSound::SeatExchange;
-                        }
-                    }
-                    _ => {
-                        self.emg_sound_keydown = EmgSoundKeyDown::K(self.time);
-                    }
+            KeyAction::ModeRight => { // 運転切換スイッチ右
+                let status = self.atc_status.get_right_status();
+                if status != self.atc_status {
+                    self.hisetsu_changeover_request = false;
                 }
+                self.atc_status = status;
+                info!("[ATCStatusChange] {:?}", self.atc_status);
             }
-            AtsKey::L => { // 0 非常放送 非常ブレーキ
-                if let EmgSoundKeyDown::None = self.emg_sound_keydown {
-                    self.emg_sound_keydown = EmgSoundKeyDown::L(self.time);
-                }
-                match self.emg_sound_keydown {
-                    EmgSoundKeyDown::L(time) => {
-                        if (self.time - time) > 1000 {
-                            self.emg_sound = EmgSound::EmergencyBrake;
-                        }
-                    }
-                    _ => {
-                        self.emg_sound_keydown = EmgSoundKeyDown::L(self.time);
-                    }
-                }
+            KeyAction::EmgSignalWait => self.press_emg_sound_key(EmgSoundKeyDown::H, EmgSound::SignalWait),
+            KeyAction::EmgEmergencyCase => self.press_emg_sound_key(EmgSoundKeyDown::I, EmgSound::EmergencyCase),
+            KeyAction::EmgEmergencyStop => self.press_emg_sound_key(EmgSoundKeyDown::J, EmgSound::EmergencyStop),
+            KeyAction::EmgSeatExchange => self.press_emg_sound_key(EmgSoundKeyDown::K, EmgSound::SeatExchange),
+            KeyAction::EmgEmergencyBrake => self.press_emg_sound_key(EmgSoundKeyDown::L, EmgSound::EmergencyBrake),
+            KeyAction::AtoDeparture if is_door_interlocked(self, self.time) => {
+                info!("[DoorInterlock] 戸閉保安が作動中のため、ATO出発を受け付けません。");
+                self.tims.key_down(key);
+                return;
             }
-            _ => {}
+            KeyAction::AtoDeparture | KeyAction::None => {}
         }
         self.tims.key_down(key);
         self.ato.key_down(key);
     }
     fn key_up(&mut self, key: AtsKey) {
         info!("called KeyUp( {key:?} )");
-        match key {
-            AtsKey::H | AtsKey::I | AtsKey::J | AtsKey::K | AtsKey::L => {
-                self.emg_sound_keydown = EmgSoundKeyDown::None
-            }
-            _ => {}
+        if self.settings.get_key_action(key).is_emg_sound() {
+            self.emg_sound_keydown = EmgSoundKeyDown::None
         }
         self.tims.key_up(key);
         self.ato.key_up(key);
@@ -573,6 +900,11 @@ impl BveAts for ULineATC {
     }
     fn door_open(&mut self) {
         info!("called DoorOpen()");
+        if self.settings.door.enable && !self.is_door_open {
+            info!("[DoorInterlock] 作動");
+        }
+        self.is_door_open = true;
+        self.door_closed_time = None;
         self.wait_door_close_security = false;
         self.door_close_time = 0;
         self.tims.door_open();
@@ -580,21 +912,70 @@ impl BveAts for ULineATC {
     }
     fn door_close(&mut self) {
         info!("called DoorClose()");
+        if self.is_door_open {
+            self.door_closed_time = Some(self.time);
+        }
+        self.is_door_open = false;
         self.wait_door_close_security = true;
         self.tims.door_close();
         self.ato.door_close();
     }
     fn set_signal(&mut self, signal: i32) {
         info!("called SetSignal( {signal} )");
-        if (0..=7).contains(&signal) {
-            self.now_signal = unsafe { std::mem::transmute(signal as u8) };
-            self.is_changing_signal = true;
-            self.tims.set_signal(signal);
-            self.ato.set_signal(signal);
+        match self.settings.signal.get_signal(signal) {
+            Some(atc_signal) => {
+                if !self.settings.signal.index.contains_key(&signal.to_string()) {
+                    warn!("対応のない信号インデックスです。({signal}) {atc_signal:?} として扱います。");
+                }
+                if atc_signal.getSpeed() < self.now_signal.getSpeed() && (atc_signal.getSpeed() as f32) < self.speed {
+                    self.caution_downgrade = true;
+                }
+                self.now_signal = atc_signal;
+                self.is_changing_signal = true;
+                self.tims.set_signal(signal);
+                self.ato.set_signal(signal);
+            }
+            None => {
+                warn!("対応のない信号インデックスを無視しました。({signal})");
+            }
         }
     }
     fn set_beacon_data(&mut self, data: AtsBeaconData) {
         info!("called SetBeaconData( {data:?} )");
+        if data.beacon_type == 22 { // 車両プロファイルの選択
+            match self.settings_file.select_by_beacon(data.optional).map(|profile| profile.name.clone()) {
+                Some(name) => {
+                    if self.select_profile(Some(name)) {
+                        self.check_settings();
+                    }
+                },
+                None => warn!("[Profile] 地上子の番号に対応する車両プロファイルがありません。({})", data.optional),
+            }
+        }
+        match data.beacon_type {
+            30 => { // 勾配
+                self.gradient.set_from_beacon(data.optional);
+                info!("[Gradient] {}‰", self.gradient.get_gradient());
+            }
+            31 => { // 勾配の変化点
+                self.gradient.add_change_from_beacon(data.optional, self.location);
+                info!("[Gradient] 変化点 {:?}", self.gradient.get_changes());
+            }
+            _ => {}
+        }
+        if self.settings.pattern.enable {
+            match data.beacon_type {
+                20 => { // パターン式ATC 目標設定
+                    self.pattern_target = PatternTarget::from_beacon(data.optional, self.location);
+                    info!("[ATCPattern] {:?}", self.pattern_target);
+                }
+                21 => { // パターン式ATC 目標解除
+                    self.pattern_target = None;
+                    info!("[ATCPattern] {:?}", self.pattern_target);
+                }
+                _ => {}
+            }
+        }
         self.tims.set_beacon_data(data);
         self.ato.set_beacon_data(data);
     }
@@ -614,24 +995,48 @@ impl Default for ULineATC {
             atc_status: AtcStatus::default(),
             enable_01kakunin_unten: false,
             enable_02hijo_unten: false,
+            hisetsu_changeover_request: false,
+            caution_downgrade: false,
+            caution_time: None,
+            pattern_target: None,
+            pattern_speed: None,
             emg_sound: EmgSound::default(),
             emg_sound_keydown: EmgSoundKeyDown::default(),
             time: 0,
             speed: 0.0,
+            location: 0.0,
             is_emg_brake_sound: false,
             is_constant_control: false,
             is_holding_control: false,
             tims_panel: Box::new([0; ELAPSE_PANEL_SIZE]),
             tims_panel_updated_time: 0,
             atc_disable: false,
+            atc_release_request_time: None,
+            atc_release_history: Vec::new(),
             ato: ULineATO::default(),
+            tasc_report: TascReport::default(),
+            gradient: GradientTracker::default(),
             wait_door_close_security: false,
             door_close_time: 0,
+            is_door_open: false,
+            door_closed_time: None,
             settings: Settings::default(),
+            settings_path: None,
+            settings_modified: None,
+            settings_load_error: None,
+            settings_file: SettingsFile::default(),
+            profile_name: None,
+            settings_error: false,
+            output: OutputLayout::default(),
             before_time: 0,
             before_speed: 0.0,
             before_acceleration: 0.0,
             constant_target_speed: 0.0,
+            constant_controller: SpeedController::default(),
+            constant_notch: 0.0,
+            holding_base_speed: 0.0,
+            holding_controller: SpeedController::default(),
+            holding_notch: 0.0,
         }
     }
 }
\ No newline at end of file
@@ -1 +1,4 @@
-pub mod uline_ato;
\ No newline at end of file
+pub mod brake_model;
+pub mod output_shaper;
+pub mod tasc_report;
+pub mod uline_ato;
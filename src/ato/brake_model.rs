@@ -0,0 +1,159 @@
+//! TASCのブレーキモデル
+//!
+//! ATO運転中のブレーキで実際に得られた減速度をブレーキノッチごとに学習し、
+//! 必要な減速度からブレーキノッチを選択する。学習した表は車両プロファイルごとにファイルへ保存する。
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::settings::TASCSettings;
+
+/// ブレーキノッチごとの減速度の表
+#[derive(Debug)]
+#[derive(Serialize, Deserialize)]
+#[derive(Clone)]
+pub struct BrakeModel {
+    /// 出力ブレーキノッチごとの減速度 (先頭はノッチ0) [km/h/s]
+    pub deceleration: Vec<f32>,
+    /// 出力ブレーキノッチごとの測定回数
+    pub samples: Vec<u32>,
+    /// 前回の測定 (時刻[ms], 速度[km/h], ブレーキノッチ, ノッチが変化した時刻[ms])
+    #[serde(skip)]
+    last: Option<(i32, f32, i32, i32)>,
+}
+impl Default for BrakeModel {
+    fn default() -> Self {
+        Self::new(0, 0.0)
+    }
+}
+impl BrakeModel {
+    /// 最大の減速度からノッチに比例する表を作成する関数
+    pub fn new(brake_notches: i32, max_deceleration: f32) -> Self {
+        let brake_notches = brake_notches.max(0);
+        Self {
+            deceleration: (0..=brake_notches).map(|notch| max_deceleration * notch as f32 / brake_notches.max(1) as f32).collect(),
+            samples: vec![0; brake_notches as usize + 1],
+            last: None,
+        }
+    }
+    /// ブレーキノッチ数
+    pub fn get_brake_notches(&self) -> i32 {
+        self.deceleration.len() as i32 - 1
+    }
+    /// 実際の出力から減速度を測定して表に反映する関数
+    ///
+    /// ノッチが変化してから `learning_settle_time` が経過し、力行していない場合のみ測定する。
+    /// 勾配による加速度 (下り勾配で正) [km/h/s] は測定値から除く。
+    pub fn observe(&mut self, settings: &TASCSettings, time: i32, speed: f32, power: i32, brake: i32, gradient_acceleration: f32) {
+        let changed_time = match self.last {
+            Some((_, _, last_brake, changed_time)) if last_brake == brake => changed_time,
+            _ => time,
+        };
+        if let Some((last_time, last_speed, last_brake, _)) = self.last {
+            let delta = time - last_time;
+            let is_valid = last_brake == brake
+                && power == 0
+                && brake > 0 && brake <= self.get_brake_notches()
+                && delta > 0
+                && time - changed_time >= settings.learning_settle_time
+                && speed >= settings.learning_min_speed;
+            if is_valid {
+                let measured = (last_speed - speed) / (delta as f32 / 1000.0) + gradient_acceleration;
+                let samples = &mut self.samples[brake as usize];
+                *samples += 1;
+                // 測定回数が少ない間は平均とし、初期値の影響を残さない
+                let rate = settings.learning_rate.max(1.0 / *samples as f32);
+                let deceleration = &mut self.deceleration[brake as usize];
+                *deceleration += (measured - *deceleration) * rate;
+            }
+        }
+        self.last = Some((time, speed, brake, changed_time));
+    }
+    /// 測定を中断する関数 (ATO以外で運転している間など)
+    pub fn reset_observation(&mut self) {
+        self.last = None;
+    }
+    /// ブレーキノッチの選択に使用する減速度の表を取得する関数
+    ///
+    /// 測定していないノッチは、測定したノッチの1ノッチあたりの減速度の平均から推定する。
+    /// 測定誤差で逆転した場合も単調増加とみなす。
+    pub fn get_deceleration_table(&self) -> Vec<f32> {
+        let learned: Vec<f32> = self.deceleration.iter().zip(&self.samples).enumerate()
+            .filter(|(notch, (_, samples))| *notch > 0 && **samples > 0)
+            .map(|(notch, (deceleration, _))| deceleration / notch as f32)
+            .collect();
+        let per_notch = (!learned.is_empty()).then(|| learned.iter().sum::<f32>() / learned.len() as f32);
+        let mut max = 0.0f32;
+        self.deceleration.iter().zip(&self.samples).enumerate()
+            .map(|(notch, (deceleration, samples))| {
+                let deceleration = match per_notch {
+                    Some(per_notch) if *samples == 0 => per_notch * notch as f32,
+                    _ => *deceleration,
+                };
+                max = max.max(deceleration);
+                max
+            })
+            .collect()
+    }
+    /// 必要な減速度を得られるブレーキノッチを求める関数 (ノッチ間は線形補間する)
+    pub fn get_notch(&self, deceleration: f32) -> f32 {
+        let brake_notches = self.get_brake_notches();
+        if brake_notches <= 0 {
+            return 0.0;
+        }
+        let table = self.get_deceleration_table();
+        for notch in 1..=brake_notches as usize {
+            let (lower, upper) = (table[notch - 1], table[notch]);
+            if upper <= lower {
+                if notch == brake_notches as usize {
+                    return notch as f32;
+                }
+                continue;
+            }
+            if deceleration <= upper || notch == brake_notches as usize {
+                return (notch - 1) as f32 + (deceleration - lower) / (upper - lower);
+            }
+        }
+        brake_notches as f32
+    }
+}
+
+/// 車両プロファイルごとのブレーキモデルを保存するファイル
+#[derive(Debug, Default)]
+#[derive(Serialize, Deserialize)]
+#[derive(Clone)]
+pub struct BrakeModelFile {
+    /// プロファイル名ごとのブレーキモデル (プロファイルを使用しない場合は "default")
+    #[serde(default)]
+    pub profiles: BTreeMap<String, BrakeModel>,
+}
+impl BrakeModelFile {
+    /// プロファイルを使用しない場合の名前
+    pub const DEFAULT_PROFILE: &'static str = "default";
+
+    /// ファイルを読み込む関数 (ファイルがない場合は空)
+    pub fn load(path: &Path) -> Result<Self, String> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| format!("ブレーキモデルの読み込みに失敗しました。({:?}) {err}", path))?;
+        toml::from_str(&text).map_err(|err| format!("ブレーキモデルのパースに失敗しました。({:?}) {err}", path))
+    }
+    /// ファイルに書き込む関数
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let text = toml::to_string(self).map_err(|err| format!("ブレーキモデルの変換に失敗しました。 {err}"))?;
+        std::fs::write(path, text).map_err(|err| format!("ブレーキモデルの書き込みに失敗しました。({:?}) {err}", path))
+    }
+    /// プロファイルのブレーキモデルを取得する関数 (ノッチ数が異なる場合は使用しない)
+    pub fn get_model(&self, profile: Option<&str>, brake_notches: i32) -> Option<&BrakeModel> {
+        self.profiles.get(profile.unwrap_or(Self::DEFAULT_PROFILE))
+            .filter(|model| model.get_brake_notches() == brake_notches && model.samples.len() == model.deceleration.len())
+    }
+    /// プロファイルのブレーキモデルを設定する関数
+    pub fn set_model(&mut self, profile: Option<&str>, model: BrakeModel) {
+        self.profiles.insert(profile.unwrap_or(Self::DEFAULT_PROFILE).to_string(), model);
+    }
+}
@@ -0,0 +1,112 @@
+//! ATOの出力ノッチの整形
+//!
+//! ATOの各制御が求めたノッチを、1秒間に変化させる段数で制限して衝動(ジャーク)を抑える。
+//! 力行からブレーキへは力行を切ってから一定時間後に、ブレーキから力行へは一定時間保持したブレーキを
+//! 緩解してから切り換える。非常ブレーキは制限せずにそのまま出力する。
+//! 変化の段数と時間の設定が全て0 (既定) の場合は整形せずにそのまま出力する。
+
+use bveats_rs::AtsHandles;
+
+use crate::settings::ATOSettings;
+
+/// ATOの出力ノッチの整形
+#[derive(Debug, Default)]
+#[derive(Clone)]
+pub struct OutputShaper {
+    /// 出力している力行ノッチ (変化の途中は小数)
+    power: f32,
+    /// 出力しているブレーキノッチ (変化の途中は小数)
+    brake: f32,
+    /// 前回の時刻 [ms]
+    before_time: Option<i32>,
+    /// 最後に力行していた時刻 [ms]
+    power_on_time: Option<i32>,
+    /// ブレーキを投入した時刻 [ms]
+    brake_on_time: Option<i32>,
+}
+
+impl OutputShaper {
+    /// 出力しているノッチを取得する関数 (力行, ブレーキ)
+    pub fn get_notches(&self) -> (i32, i32) {
+        (self.power.round() as i32, self.brake.round() as i32)
+    }
+    /// 出力を0にして初期状態に戻す関数 (ATO以外で運転している間など)
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+    /// 目標のノッチを整形して出力するノッチを求める関数 (Elapse毎に呼び出す)
+    pub fn shape(&mut self, settings: &ATOSettings, output_brake_notches: i32, time: i32, target: AtsHandles) -> AtsHandles {
+        // 整形しない場合は、後から整形を有効にしても出力が急変しないように出力しているノッチだけ記録する
+        if !is_shaped(settings) {
+            *self = Self {
+                power: target.power.max(0) as f32,
+                brake: target.brake.clamp(0, output_brake_notches) as f32,
+                before_time: Some(time),
+                ..Default::default()
+            };
+            return target;
+        }
+        let delta = self.before_time.map_or(0, |before_time| (time - before_time).max(0)) as f32 / 1000.0;
+        self.before_time = Some(time);
+
+        // 非常ブレーキは制限しない
+        if target.brake > output_brake_notches {
+            if self.brake == 0.0 {
+                self.brake_on_time = Some(time);
+            }
+            self.power = 0.0;
+            self.brake = output_brake_notches as f32;
+            return AtsHandles { power: 0, ..target };
+        }
+
+        let mut target_power = target.power.max(0) as f32;
+        let mut target_brake = target.brake.max(0) as f32;
+        if target_brake > 0.0 {
+            target_power = 0.0;
+        }
+        // 力行を切ってから一定時間はブレーキを投入しない
+        let is_power_off = self.power == 0.0
+            && self.power_on_time.is_none_or(|power_on_time| time - power_on_time >= settings.power_off_delay);
+        if target_brake > 0.0 && self.brake == 0.0 && !is_power_off {
+            target_brake = 0.0;
+        }
+        // ブレーキは一定時間保持し、緩解してから力行する
+        if target_power > 0.0 && self.brake > 0.0 {
+            let is_held = self.brake_on_time.is_none_or(|brake_on_time| time - brake_on_time >= settings.brake_hold_time);
+            if !is_held {
+                target_brake = self.brake;
+            }
+            target_power = 0.0;
+        }
+
+        self.power = approach(self.power, target_power, settings.power_notch_rate, delta);
+        let brake = approach(self.brake, target_brake, settings.brake_notch_rate, delta);
+        if self.brake == 0.0 && brake > 0.0 {
+            self.brake_on_time = Some(time);
+        }
+        self.brake = brake;
+        if self.power > 0.0 {
+            self.power_on_time = Some(time);
+        }
+
+        let (power, brake) = self.get_notches();
+        AtsHandles { power, brake, ..target }
+    }
+}
+
+/// 出力ノッチを整形するか (変化の段数と時間の設定が全て0の場合は整形しない)
+fn is_shaped(settings: &ATOSettings) -> bool {
+    settings.power_notch_rate > 0.0
+        || settings.brake_notch_rate > 0.0
+        || settings.power_off_delay > 0
+        || settings.brake_hold_time > 0
+}
+
+/// `value` を `target` に向けて最大 `rate` [ノッチ/s] で `delta` [s] 間変化させる関数 (`rate` が0以下の場合は制限しない)
+fn approach(value: f32, target: f32, rate: f32, delta: f32) -> f32 {
+    if rate <= 0.0 {
+        return target;
+    }
+    let step = rate * delta;
+    value + (target - value).clamp(-step, step)
+}
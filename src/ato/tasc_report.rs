@@ -0,0 +1,136 @@
+//! TASCの停止精度の評価
+//!
+//! TASCのパターンで停車した位置を停止目標(地上子の位置 + 停止目標までの距離)と比較し、
+//! セッションごとのレポートにまとめる。
+
+use std::path::PathBuf;
+
+/// 停止精度の判定
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopAccuracy {
+    /// ±35cm以内
+    Accurate,
+    /// ±1m以内
+    Near,
+    /// 1mを超えて行き過ぎ
+    Overrun,
+    /// 1mを超えて手前に停車
+    Short,
+}
+impl StopAccuracy {
+    /// ±35cm以内とする誤差 [m]
+    pub const ACCURATE_LIMIT: f64 = 0.35;
+    /// ±1m以内とする誤差 [m]
+    pub const NEAR_LIMIT: f64 = 1.0;
+
+    /// 停止位置の誤差から判定する関数 (誤差は行き過ぎを正とする) [m]
+    pub fn from_error(error: f64) -> Self {
+        if error.abs() <= Self::ACCURATE_LIMIT {
+            Self::Accurate
+        } else if error.abs() <= Self::NEAR_LIMIT {
+            Self::Near
+        } else if error > 0.0 {
+            Self::Overrun
+        } else {
+            Self::Short
+        }
+    }
+    /// TIMSに表示する値 (0は表示なし)
+    pub fn to_panel(self) -> i32 {
+        match self {
+            Self::Accurate => 1,
+            Self::Near => 2,
+            Self::Overrun => 3,
+            Self::Short => 4,
+        }
+    }
+    /// レポートに記載する名前
+    pub fn get_name(self) -> &'static str {
+        match self {
+            Self::Accurate => "±35cm以内",
+            Self::Near => "±1m以内",
+            Self::Overrun => "行き過ぎ",
+            Self::Short => "手前停止",
+        }
+    }
+}
+
+/// TASCのパターンで停車した結果
+#[derive(Debug, Clone, PartialEq)]
+pub struct TascStop {
+    /// 停車した時刻 [ms]
+    pub time: i32,
+    /// TASC停車駅の番号 (地上子で設定されていない場合はTIMSの次の停車駅, どちらもない場合はNone)
+    pub station: Option<i32>,
+    /// 停止目標の位置 [m]
+    pub target_location: f64,
+    /// 停車した位置 [m]
+    pub stop_location: f64,
+}
+impl TascStop {
+    /// 停止位置の誤差を取得する関数 (行き過ぎを正とする) [m]
+    pub fn get_error(&self) -> f64 {
+        self.stop_location - self.target_location
+    }
+    /// 停止精度を判定する関数
+    pub fn get_accuracy(&self) -> StopAccuracy {
+        StopAccuracy::from_error(self.get_error())
+    }
+}
+
+/// レポートの1件分
+#[derive(Debug, Clone, PartialEq)]
+pub struct TascReportEntry {
+    /// 停車した結果
+    pub stop: TascStop,
+    /// TIMSの始発駅
+    pub start_station: i32,
+    /// TIMSの行先
+    pub destination: i32,
+}
+
+/// セッション中のTASCの停止精度のレポート
+#[derive(Debug, Default)]
+#[derive(Clone)]
+pub struct TascReport {
+    /// レポートを書き込むファイルのパス
+    pub path: Option<PathBuf>,
+    /// 停車した結果
+    pub entries: Vec<TascReportEntry>,
+}
+impl TascReport {
+    /// 人が読める形式のレポートを作成する関数
+    pub fn to_text(&self) -> String {
+        let mut text = String::from("# TASC 停止精度レポート\n");
+        if self.entries.is_empty() {
+            text.push_str("停車の記録はありません。\n");
+            return text;
+        }
+        let errors: Vec<f64> = self.entries.iter().map(|entry| entry.stop.get_error()).collect();
+        let mean = errors.iter().sum::<f64>() / errors.len() as f64;
+        let max = errors.iter().fold(0.0f64, |max, error| max.max(error.abs()));
+        text.push_str(&format!("{}回停車 平均誤差 {mean:+.2}m 最大誤差 {max:.2}m\n", self.entries.len()));
+        for accuracy in [StopAccuracy::Accurate, StopAccuracy::Near, StopAccuracy::Overrun, StopAccuracy::Short] {
+            let count = self.entries.iter().filter(|entry| entry.stop.get_accuracy() == accuracy).count();
+            text.push_str(&format!("{}: {count}回\n", accuracy.get_name()));
+        }
+        text.push_str("\n時刻[ms],駅,始発駅,行先,停止目標[m],停車位置[m],誤差[m],判定\n");
+        for entry in &self.entries {
+            let stop = &entry.stop;
+            text.push_str(&format!("{},{},{},{},{:.2},{:.2},{:+.2},{}\n",
+                stop.time,
+                stop.station.map_or(String::new(), |station| station.to_string()),
+                entry.start_station,
+                entry.destination,
+                stop.target_location,
+                stop.stop_location,
+                stop.get_error(),
+                stop.get_accuracy().get_name()));
+        }
+        text
+    }
+    /// 停車した結果を追加する関数
+    pub fn push(&mut self, entry: TascReportEntry) {
+        self.entries.push(entry);
+    }
+}
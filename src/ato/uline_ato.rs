@@ -1,10 +1,14 @@
 
-use bveats_rs::{AtsConstantSpeed, AtsHandles, AtsKey, AtsVehicleState, BveAts};
+use bveats_rs::{AtsConstantSpeed, AtsHandles, AtsVehicleState, BveAts};
 use log::info;
-use crate::settings::Settings;
+use crate::settings::{KeyAction, Settings, TascPattern};
 use crate::timer::Timer;
+use crate::ato::brake_model::BrakeModel;
+use crate::ato::output_shaper::OutputShaper;
+use crate::ato::tasc_report::TascStop;
 
 use crate::atc::atc_signal::AtcSignal;
+use crate::atc::gradient::{get_feed_forward_acceleration, get_feed_forward_notches, get_gradient_acceleration, GradientTracker};
 
 
 /// ATOの状態を表す
@@ -29,6 +33,7 @@ enum ATOStatus {
     /// 過速防止制御(P3)
     P3(i32, f32, f32),
 }
+#[allow(clippy::derivable_impls)]
 impl Default for ATOStatus {
     fn default() -> Self {
         Self::Stop
@@ -48,6 +53,16 @@ pub struct ULineATO {
     before_acceleration: f32,
     operation_timer: Timer,
     is_not_one_time_braking: bool,
+    /// TASCで停車する駅の番号 (地上子で設定し、TASCのパターンで停車すると解除する)
+    pub tasc_station: Option<i32>,
+    /// TASCのパターンで停車した結果 (ATCで取り出すまで保持する)
+    pub tasc_stop: Option<TascStop>,
+    /// ブレーキノッチごとの減速度の表
+    pub brake_model: BrakeModel,
+    /// 勾配地上子で受信した勾配 (ATCから受け取る)
+    pub gradient: GradientTracker,
+    /// 出力ノッチの変化の制限
+    pub output_shaper: OutputShaper,
 
     pub settings: Settings,
 }
@@ -64,6 +79,11 @@ impl Default for ULineATO {
             before_acceleration: 0.0,
             operation_timer: Timer::new(200),
             is_not_one_time_braking: false,
+            tasc_station: None,
+            tasc_stop: None,
+            brake_model: Default::default(),
+            gradient: Default::default(),
+            output_shaper: Default::default(),
             settings: Default::default(),
         }
     }
@@ -93,6 +113,22 @@ impl BveAts for ULineATO {
             self.now_power = 0;
         }
 
+        // TASCのパターンで停車した場合は停止位置を評価する
+        if state.speed == 0.0 && self.before_speed != 0.0 {
+            if let Some(target_location) = self.get_tasc_target_location() {
+                let stop = TascStop {
+                    time: state.time,
+                    station: self.tasc_station,
+                    target_location,
+                    stop_location: state.location,
+                };
+                info!("[TASC] 停止位置の誤差 {:+.2}m ({:?})", stop.get_error(), stop.get_accuracy());
+                self.tasc_stop = Some(stop);
+                // 停車駅の設定は次の駅に持ち越さない
+                self.tasc_station = None;
+            }
+        }
+
         let result = match self.status {
             ATOStatus::Departure => {
                 let target_speed = self.signal.getSpeed() - 5; // ATO目標速度
@@ -129,12 +165,13 @@ impl BveAts for ULineATO {
                     self.status = status;
                 }
                 if state.speed > self.settings.ato.p2_check_speed {
-                    return AtsHandles {
+                    let result = AtsHandles {
                         brake: self.settings.vehicle.output_brake_notches + 1,
                         power: 0,
                         reverser: 1,
                         constant_speed: AtsConstantSpeed::Disable as i32,
-                    }
+                    };
+                    return self.output_shaper.shape(&self.settings.ato, self.settings.vehicle.output_brake_notches, state.time, result);
                 }
                 if beacon_location.is_nan() {
                     if let ATOStatus::TASC2(_, location, _) = &mut self.status {
@@ -254,6 +291,8 @@ impl BveAts for ULineATO {
                 }
             }
         };
+        // 衝動を抑えるためにノッチの変化を制限する
+        let result = self.output_shaper.shape(&self.settings.ato, self.settings.vehicle.output_brake_notches, state.time, result);
         self.before_ato_notch = result;
         result
     }
@@ -271,7 +310,7 @@ impl BveAts for ULineATO {
     }
 
     fn key_down(&mut self, key: bveats_rs::AtsKey) {
-        if key == AtsKey::S {
+        if self.settings.get_key_action(key) == KeyAction::AtoDeparture {
             if self.before_speed != 0.0 {
                 return;
             }
@@ -298,18 +337,22 @@ impl BveAts for ULineATO {
     }
 
     fn set_signal(&mut self, signal: i32) {
-        self.signal = unsafe { std::mem::transmute(signal as u8) };
+        if let Some(signal) = self.settings.signal.get_signal(signal) {
+            self.signal = signal;
+        }
     }
 
     fn set_beacon_data(&mut self, data: bveats_rs::AtsBeaconData) {
         match data.beacon_type {
             1 => { // 第1パターン
-                let status = ATOStatus::TASC1(self.before_time, f32::NAN , 350.5);
+                let distance = self.get_tasc_distance(TascPattern::P1, data.optional);
+                let status = ATOStatus::TASC1(self.before_time, f32::NAN , distance);
                 info!("[ATO] {:?}→{:?}", self.status, status);
                 self.status = status;
             }
             2 => { // 第2パターン
-                let status = ATOStatus::TASC2(self.before_time, f32::NAN , 25.5);
+                let distance = self.get_tasc_distance(TascPattern::P2, data.optional);
+                let status = ATOStatus::TASC2(self.before_time, f32::NAN , distance);
                 info!("[ATO] {:?}→{:?}", self.status, status);
                 self.status = status;
             }
@@ -335,16 +378,39 @@ impl BveAts for ULineATO {
                 self.status = status;
             }
             6 => { // 90パターン
-                let status = ATOStatus::TASC90(self.before_time, f32::NAN , 600.5);
+                let distance = self.get_tasc_distance(TascPattern::P90, data.optional);
+                let status = ATOStatus::TASC90(self.before_time, f32::NAN , distance);
                 info!("[ATO] {:?}→{:?}", self.status, status);
                 self.status = status;
             }
+            7 => { // TASC停車駅の設定
+                self.tasc_station = Some(data.optional);
+                info!("[TASC] 停車駅 {}", data.optional);
+            }
             _ => {}
         }
     }
 }
 
 impl ULineATO {
+    /// TASCのパターンの停止目標の位置を取得する関数 (TASC制御中でない場合はNone)
+    fn get_tasc_target_location(&self) -> Option<f64> {
+        match self.status {
+            ATOStatus::TASC90(_, beacon_location, target_distance) |
+            ATOStatus::TASC1(_, beacon_location, target_distance) |
+            ATOStatus::TASC2(_, beacon_location, target_distance) |
+            ATOStatus::P3(_, beacon_location, target_distance) if !beacon_location.is_nan() => {
+                Some(beacon_location as f64 + target_distance as f64)
+            }
+            _ => None,
+        }
+    }
+    /// TASCのパターン地上子から停止目標までの距離を取得する関数
+    fn get_tasc_distance(&self, pattern: TascPattern, optional: i32) -> f32 {
+        let distance = self.settings.tasc.get_target_distance(pattern, self.tasc_station, optional);
+        info!("[TASC] {pattern:?} 停止目標まで {distance}m (駅: {:?}, optional: {optional})", self.tasc_station);
+        distance
+    }
     fn ato_constant_speed(&mut self, state: AtsVehicleState) -> AtsHandles {
         let delta = state.time - self.before_time;
         let acceleration_km_h_s = (state.speed - self.before_speed) / (delta as f32 / 1000.0);
@@ -358,6 +424,11 @@ impl ULineATO {
         power_notch += ((speed_diff) / 0.75).clamp(-10.0, 10.0) as i32;
         brake_notch += ((speed_diff) / 0.25).clamp(-10.0, 10.0) as i32;
 
+        // 勾配を打ち消すノッチを加える
+        let (feed_forward_power, feed_forward_brake) = get_feed_forward_notches(&self.settings, self.gradient.get_gradient());
+        power_notch += feed_forward_power.round() as i32;
+        brake_notch -= feed_forward_brake.round() as i32;
+
         self.now_power =  power_notch.clamp(0, 31);
         self.now_brake = -brake_notch.clamp(-31, 0);
 
@@ -368,9 +439,23 @@ impl ULineATO {
             constant_speed: AtsConstantSpeed::Disable as i32
         }
     }
+    /// ATO運転中の実際の出力から減速度を学習する関数
+    pub fn observe_brake(&mut self, state: AtsVehicleState, handles: AtsHandles) {
+        if !self.settings.tasc.adaptive_brake {
+            return;
+        }
+        self.update_brake_model();
+        let gradient_acceleration = get_gradient_acceleration(self.gradient.get_gradient());
+        self.brake_model.observe(&self.settings.tasc, state.time, state.speed, handles.power, handles.brake, gradient_acceleration);
+    }
+    /// ブレーキノッチ数が設定と異なる場合はブレーキモデルを作成し直す関数
+    fn update_brake_model(&mut self) {
+        let tasc = &self.settings.tasc;
+        if self.brake_model.get_brake_notches() != self.settings.vehicle.output_brake_notches {
+            self.brake_model = BrakeModel::new(self.settings.vehicle.output_brake_notches, tasc.max_deceleration);
+        }
+    }
     fn ato_tasc_with_distance(&mut self, state: AtsVehicleState, remaining_distance: f32) -> AtsHandles {
-        const MAX_DECELERATION: f32 = 3.50;
-
         let target_speed = self.ato_tasc_target_speed(remaining_distance);
         
         if target_speed.is_nan() {
@@ -385,7 +470,15 @@ impl ULineATO {
         if state.speed > 5.0 {
             output_deceleration = (1.0 / 7.2) * ((state.speed.powi(2) - self.ato_tasc_target_speed(remaining_distance / 2.0).powi(2)) / (remaining_distance / 2.0));
         }
-        let mut output_brake = output_deceleration / MAX_DECELERATION * self.settings.vehicle.output_brake_notches as f32; 
+        // 停止目標までの平均の勾配を打ち消す減速度を加える
+        let gradient = self.gradient.get_average_gradient(state.location, remaining_distance);
+        output_deceleration += get_feed_forward_acceleration(&self.settings, gradient);
+        let mut output_brake = if self.settings.tasc.adaptive_brake {
+            self.update_brake_model();
+            self.brake_model.get_notch(output_deceleration)
+        } else {
+            output_deceleration / self.settings.tasc.max_deceleration * self.settings.vehicle.output_brake_notches as f32
+        };
 
         { // 速度超過時のブレーキ補填
             output_brake -= ((target_speed - state.speed) / 1.0).clamp(-10.0, 10.0);
@@ -0,0 +1,45 @@
+//! uline.toml の項目一覧を出力するツール
+//!
+//! ```text
+//! uline-config [toml|schema] [--output <path>]
+//! ```
+//!
+//! * `toml` - コメント付きのデフォルト設定ファイルを出力する (既定)
+//! * `schema` - 設定項目のスキーマをJSONで出力する
+
+use std::path::PathBuf;
+
+use uline_atc::settings::schema::{to_default_toml, to_json_schema};
+
+fn main() {
+    let mut format = "toml".to_string();
+    let mut output: Option<PathBuf> = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--output" => match args.next() {
+                Some(path) => output = Some(PathBuf::from(path)),
+                None => {
+                    eprintln!("--output の値がありません。");
+                    std::process::exit(1);
+                }
+            },
+            "toml" | "schema" => format = arg,
+            _ => {
+                eprintln!("不明な引数です。({arg})");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let text = if format == "schema" { to_json_schema() } else { to_default_toml() };
+    match output {
+        Some(path) => {
+            if let Err(err) = std::fs::write(&path, text) {
+                eprintln!("書き込みに失敗しました。({path:?}) {err}");
+                std::process::exit(1);
+            }
+        }
+        None => print!("{text}"),
+    }
+}
@@ -0,0 +1,37 @@
+//! 記録したBVEのセッションをULineATCに再生し、記録との差分を出力する
+//! (記録ファイルのヘッダにある記録時の設定ファイルを使用する)
+//!
+//! ```text
+//! uline-replay <記録ファイル>
+//! ```
+
+use uline_atc::sim::replay::replay_with_recorded_settings;
+
+fn main() {
+    let Some(path) = std::env::args().nth(1) else {
+        eprintln!("記録ファイルを指定してください。");
+        std::process::exit(1);
+    };
+    let log = match std::fs::read_to_string(&path) {
+        Ok(log) => log,
+        Err(err) => {
+            eprintln!("記録ファイルの読み込みに失敗しました。({path}) {err}");
+            std::process::exit(1);
+        }
+    };
+
+    let mismatches = match replay_with_recorded_settings(&log) {
+        Ok(mismatches) => mismatches,
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    };
+    for mismatch in &mismatches {
+        println!("{}行目 {}ms: {}", mismatch.line, mismatch.time, mismatch.message);
+    }
+    if !mismatches.is_empty() {
+        eprintln!("{} 件の差分があります。", mismatches.len());
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,131 @@
+//! ULineATCをBVEなしで走行させるヘッドレスシミュレータ
+//!
+//! ```text
+//! uline-sim [--interval <ms>] [--duration <ms>] [--signal <index>] [--power <notch>] [--output-interval <ms>]
+//! uline-sim --scenario <path> [--output-interval <ms>]
+//! ```
+
+use std::path::PathBuf;
+
+use bveats_rs::{AtsHandles, AtsInit, AtsVehicleSpec, BveAts};
+use uline_atc::atc::uline_atc::ULineATC;
+use uline_atc::sim::{host::HeadlessHost, scenario::Scenario, vehicle::PointMassVehicle};
+
+/// コマンドライン引数
+struct Args {
+    /// Elapseの呼び出し間隔 [ms]
+    interval: i32,
+    /// シミュレーションする時間 [ms]
+    duration: i32,
+    /// 走行中に受信し続ける信号インデックス
+    signal: i32,
+    /// 投入する力行ノッチ
+    power: i32,
+    /// 結果を出力する間隔 [ms]
+    output_interval: i32,
+    /// シナリオファイル
+    scenario: Option<PathBuf>,
+}
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            interval: 10,
+            duration: 60000,
+            signal: 7,
+            power: 4,
+            output_interval: 1000,
+            scenario: None,
+        }
+    }
+}
+impl Args {
+    fn parse() -> Result<Self, String> {
+        let mut args = Self::default();
+        let mut iter = std::env::args().skip(1);
+        while let Some(name) = iter.next() {
+            let value = iter.next().ok_or(format!("{name} の値がありません。"))?;
+            if name == "--scenario" {
+                args.scenario = Some(PathBuf::from(value));
+                continue;
+            }
+            let value: i32 = value.parse().map_err(|_| format!("{name} の値が不正です。({value})"))?;
+            match name.as_str() {
+                "--interval" => args.interval = value,
+                "--duration" => args.duration = value,
+                "--signal" => args.signal = value,
+                "--power" => args.power = value,
+                "--output-interval" => args.output_interval = value,
+                _ => return Err(format!("不明な引数です。({name})")),
+            }
+        }
+        if args.interval <= 0 || args.output_interval <= 0 {
+            return Err("--interval と --output-interval は正の値を指定してください。".to_string());
+        }
+        Ok(args)
+    }
+}
+
+/// 一定間隔でCSVを出力する
+struct CsvWriter {
+    interval: i32,
+    next_time: i32,
+}
+impl CsvWriter {
+    fn new(interval: i32) -> Self {
+        println!("time,location,speed,power,brake,reverser");
+        Self { interval, next_time: 0 }
+    }
+
+    fn write<T: BveAts>(&mut self, host: &HeadlessHost<T>, handles: AtsHandles) {
+        if self.next_time <= host.vehicle.time {
+            self.next_time += self.interval;
+            println!("{},{:.2},{:.2},{},{},{}",
+                host.vehicle.time, host.vehicle.location, host.vehicle.speed,
+                handles.power, handles.brake, handles.reverser);
+        }
+    }
+}
+
+fn main() {
+    let args = match Args::parse() {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    };
+
+    if let Some(path) = &args.scenario {
+        let scenario = match Scenario::load(path) {
+            Ok(scenario) => scenario,
+            Err(err) => {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+        };
+        let mut host = scenario.create_host(ULineATC::default());
+        let mut writer = CsvWriter::new(args.output_interval);
+        scenario.run(&mut host, |host| writer.write(host, host.handles));
+        return;
+    }
+
+    let spec = AtsVehicleSpec {
+        brake_notches: 7,
+        power_notches: 4,
+        ats_notch: 1,
+        b67_notch: 5,
+        cars: 6,
+    };
+    let mut host = HeadlessHost::new(ULineATC::default(), spec, PointMassVehicle::default(), args.interval);
+    host.start(AtsInit::Removed);
+    host.ats.set_signal(args.signal);
+    host.ats.set_reverser(1);
+    host.ats.set_power(args.power);
+
+    let mut writer = CsvWriter::new(args.output_interval);
+    while host.vehicle.time < args.duration {
+        let handles = host.step();
+        writer.write(&host, handles);
+    }
+    host.dispose();
+}
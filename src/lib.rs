@@ -1,13 +1,15 @@
 #![allow(non_snake_case)]
 
-mod atc;
-mod ato;
+pub mod atc;
+pub mod ato;
 mod tims;
-mod settings;
+pub mod output;
+pub mod settings;
 mod timer;
+pub mod sim;
 
-#[cfg(windows)]
 use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
 #[cfg(windows)]
 use std::sync::LazyLock;
 #[cfg(windows)]
@@ -17,7 +19,40 @@ use winapi::um::winnt::DLL_PROCESS_ATTACH;
 
 use ::bveats_rs::*;
 
-ats_main!(crate::atc::uline_atc::ULineATC);
+use crate::settings::Settings;
+
+ats_main!(crate::atc::uline_atc::ULineATC, crate::get_record_path(), crate::get_record_header());
+
+/// DLLが置かれているディレクトリを取得する関数
+#[cfg(windows)]
+pub(crate) fn get_dll_directory() -> Option<PathBuf> {
+    (*DLL_PATH).clone()
+}
+/// DLLが置かれているディレクトリを取得する関数 (Windows以外ではカレントディレクトリ)
+#[cfg(not(windows))]
+pub(crate) fn get_dll_directory() -> Option<PathBuf> {
+    Some(PathBuf::new())
+}
+
+/// セッションを記録するファイルのパスを取得する関数 (設定で記録が有効な場合のみ)
+fn get_record_path() -> Option<PathBuf> {
+    // 設定ファイルの読み込みエラーを出力するため、先にロガーを初期化する
+    let _ = colog::default_builder().try_init();
+    let dll_directory = get_dll_directory()?;
+    let settings = Settings::load(&dll_directory.join("uline.toml"));
+    if !settings.record.enable {
+        return None;
+    }
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+    Some(dll_directory.join(format!("uline_session_{now}.log")))
+}
+
+/// セッションの記録ファイルのヘッダを取得する関数 (再生時に使用する記録時の設定ファイルの内容)
+fn get_record_header() -> String {
+    let path = get_dll_directory().unwrap_or_default().join("uline.toml");
+    let text = std::fs::read_to_string(&path).unwrap_or_default();
+    sim::replay::to_record_header(&path, &text)
+}
 
 #[cfg(windows)]
 static DLL_PATH: LazyLock<Option<PathBuf>> = LazyLock::new(|| {
@@ -0,0 +1,285 @@
+//! パネル・サウンドの出力先の対応表
+//!
+//! 各機能は名前の付いた出力に書き込み、BVEのインデックスへの対応は `uline.toml` の `[output]` で変更できる。
+
+use crate::settings::OutputSettings;
+
+/// パネルの出力
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PanelOutput {
+    /// 力行ノッチ
+    PowerNotch,
+    /// ブレーキノッチ
+    BrakeNotch,
+    /// 定速
+    ConstantSpeed,
+    /// 抑速
+    HoldingSpeed,
+    /// ATC信号[02]
+    Signal02,
+    /// ATC信号[01]
+    Signal01,
+    /// ATC信号[15]
+    Signal15,
+    /// ATC信号[25]
+    Signal25,
+    /// ATC信号[45]
+    Signal45,
+    /// ATC信号[60]
+    Signal60,
+    /// ATC信号[75]
+    Signal75,
+    /// ATC信号[90]
+    Signal90,
+    /// 異常 (設定ファイルの異常)
+    SettingsError,
+    /// 非常運転
+    HijoUnten,
+    /// 確認運転
+    KakuninUnten,
+    /// 非設
+    Hisetsu,
+    /// 入換
+    Irekae,
+    /// ATC
+    Atc,
+    /// ATO
+    Ato,
+    /// ATC開放 (点滅)
+    AtcRelease,
+    /// 速度 (0.1km/h単位)
+    Speed,
+    /// BC圧力
+    BcPressure,
+    /// MR圧力
+    MrPressure,
+    /// 電流
+    Current,
+    /// 入換信号[15]
+    Irekae15,
+    /// 入換信号[25]
+    Irekae25,
+    /// パターン速度 (0.1km/h単位)
+    PatternSpeed,
+    /// パターン式ATC
+    Pattern,
+    /// 戸閉保安
+    DoorInterlock,
+    /// 運番10位
+    OperationNumber10,
+    /// 運番1位
+    OperationNumber1,
+    /// 列車種別
+    TrainType,
+    /// 行先
+    Destination,
+    /// 時10位
+    Hour10,
+    /// 時1位
+    Hour1,
+    /// 分10位
+    Minute10,
+    /// 分1位
+    Minute1,
+    /// 秒10位
+    Second10,
+    /// 秒1位
+    Second1,
+    /// 時速10位
+    SpeedDigit10,
+    /// 時速1位
+    SpeedDigit1,
+    /// 位置プレフィックス
+    DistancePrefix,
+    /// 位置10位
+    Distance10,
+    /// 位置1位
+    Distance1,
+    /// 位置0.1位
+    Distance01,
+    /// 位置0.01位
+    Distance001,
+    /// TASC停止精度の判定
+    TascAccuracy,
+    /// TASC停止位置の誤差 (cm単位)
+    TascError,
+}
+impl PanelOutput {
+    /// 全てのパネルの出力
+    pub const ALL: [PanelOutput; 48] = [
+        PanelOutput::PowerNotch, PanelOutput::BrakeNotch, PanelOutput::ConstantSpeed, PanelOutput::HoldingSpeed,
+        PanelOutput::Signal02, PanelOutput::Signal01, PanelOutput::Signal15, PanelOutput::Signal25,
+        PanelOutput::Signal45, PanelOutput::Signal60, PanelOutput::Signal75, PanelOutput::Signal90,
+        PanelOutput::SettingsError, PanelOutput::HijoUnten, PanelOutput::KakuninUnten, PanelOutput::Hisetsu,
+        PanelOutput::Irekae, PanelOutput::Atc, PanelOutput::Ato, PanelOutput::AtcRelease,
+        PanelOutput::Speed, PanelOutput::BcPressure, PanelOutput::MrPressure, PanelOutput::Current,
+        PanelOutput::Irekae15, PanelOutput::Irekae25, PanelOutput::PatternSpeed, PanelOutput::Pattern,
+        PanelOutput::DoorInterlock,
+        PanelOutput::OperationNumber10, PanelOutput::OperationNumber1, PanelOutput::TrainType, PanelOutput::Destination,
+        PanelOutput::Hour10, PanelOutput::Hour1, PanelOutput::Minute10, PanelOutput::Minute1,
+        PanelOutput::Second10, PanelOutput::Second1, PanelOutput::SpeedDigit10, PanelOutput::SpeedDigit1,
+        PanelOutput::DistancePrefix, PanelOutput::Distance10, PanelOutput::Distance1, PanelOutput::Distance01,
+        PanelOutput::Distance001, PanelOutput::TascAccuracy, PanelOutput::TascError,
+    ];
+    /// 既定のパネルインデックス
+    pub fn get_default_index(&self) -> i32 {
+        match self {
+            PanelOutput::PowerNotch => 9,
+            PanelOutput::BrakeNotch => 10,
+            PanelOutput::ConstantSpeed => 19,
+            PanelOutput::HoldingSpeed => 20,
+            PanelOutput::Signal02 => 31,
+            PanelOutput::Signal01 => 32,
+            PanelOutput::Signal15 => 33,
+            PanelOutput::Signal25 => 34,
+            PanelOutput::Signal45 => 35,
+            PanelOutput::Signal60 => 36,
+            PanelOutput::Signal75 => 37,
+            PanelOutput::Signal90 => 38,
+            PanelOutput::SettingsError => 39,
+            PanelOutput::HijoUnten => 40,
+            PanelOutput::KakuninUnten => 41,
+            PanelOutput::Hisetsu => 42,
+            PanelOutput::Irekae => 43,
+            PanelOutput::Atc => 44,
+            PanelOutput::Ato => 45,
+            PanelOutput::AtcRelease => 46,
+            PanelOutput::Speed => 49,
+            PanelOutput::BcPressure => 50,
+            PanelOutput::MrPressure => 51,
+            PanelOutput::Current => 52,
+            PanelOutput::Irekae15 => 53,
+            PanelOutput::Irekae25 => 54,
+            PanelOutput::PatternSpeed => 55,
+            PanelOutput::Pattern => 56,
+            PanelOutput::DoorInterlock => 57,
+            PanelOutput::OperationNumber10 => 101,
+            PanelOutput::OperationNumber1 => 102,
+            PanelOutput::TrainType => 103,
+            PanelOutput::Destination => 104,
+            PanelOutput::Hour10 => 107,
+            PanelOutput::Hour1 => 108,
+            PanelOutput::Minute10 => 109,
+            PanelOutput::Minute1 => 110,
+            PanelOutput::Second10 => 111,
+            PanelOutput::Second1 => 112,
+            PanelOutput::SpeedDigit10 => 113,
+            PanelOutput::SpeedDigit1 => 114,
+            PanelOutput::DistancePrefix => 116,
+            PanelOutput::Distance10 => 117,
+            PanelOutput::Distance1 => 118,
+            PanelOutput::Distance01 => 119,
+            PanelOutput::Distance001 => 120,
+            PanelOutput::TascAccuracy => 121,
+            PanelOutput::TascError => 122,
+        }
+    }
+}
+
+/// サウンドの出力
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SoundOutput {
+    /// ATC現示変化ベル
+    AtcBell,
+    /// ATC速度制限警報ブザー
+    AtcBuzzer,
+    /// ATC注意喚起警報チャイム
+    CautionChime,
+    /// ATC切換要請警報ブザー
+    ChangeoverBuzzer,
+    /// 戸閉から10秒後になる緩解音
+    DoorCloseRelease,
+    /// 車外回送放送
+    OutOfService,
+    /// 非常放送 信号待ち
+    EmgSignalWait,
+    /// 非常放送 急病人対応
+    EmgEmergencyCase,
+    /// 非常放送 緊急停止
+    EmgEmergencyStop,
+    /// 非常放送 シート交換
+    EmgSeatExchange,
+    /// 非常放送 非常ブレーキ
+    EmgEmergencyBrake,
+    /// 急停車放送
+    EmgBrake,
+}
+impl SoundOutput {
+    /// 全てのサウンドの出力
+    pub const ALL: [SoundOutput; 12] = [
+        SoundOutput::AtcBell, SoundOutput::AtcBuzzer, SoundOutput::CautionChime, SoundOutput::ChangeoverBuzzer,
+        SoundOutput::DoorCloseRelease, SoundOutput::OutOfService, SoundOutput::EmgSignalWait, SoundOutput::EmgEmergencyCase,
+        SoundOutput::EmgEmergencyStop, SoundOutput::EmgSeatExchange, SoundOutput::EmgEmergencyBrake, SoundOutput::EmgBrake,
+    ];
+    /// 既定のサウンドインデックス
+    pub fn get_default_index(&self) -> i32 {
+        match self {
+            SoundOutput::AtcBell => 2,
+            SoundOutput::AtcBuzzer => 3,
+            SoundOutput::CautionChime => 4,
+            SoundOutput::ChangeoverBuzzer => 5,
+            SoundOutput::DoorCloseRelease => 20,
+            SoundOutput::OutOfService => 100,
+            SoundOutput::EmgSignalWait => 101,
+            SoundOutput::EmgEmergencyCase => 102,
+            SoundOutput::EmgEmergencyStop => 103,
+            SoundOutput::EmgSeatExchange => 104,
+            SoundOutput::EmgEmergencyBrake => 105,
+            SoundOutput::EmgBrake => 106,
+        }
+    }
+}
+
+/// 出力の名前からインデックスを決める関数 (設定がない場合は既定のインデックス, 負の値は出力しない)
+fn resolve_index(overrides: &std::collections::BTreeMap<String, i32>, name: String, default_index: i32) -> Option<usize> {
+    let index = overrides.get(&name).copied().unwrap_or(default_index);
+    usize::try_from(index).ok()
+}
+
+/// パネル・サウンドの出力先の対応表
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct OutputLayout {
+    /// PanelOutputごとのパネルインデックス
+    panel: Vec<Option<usize>>,
+    /// SoundOutputごとのサウンドインデックス
+    sound: Vec<Option<usize>>,
+}
+impl Default for OutputLayout {
+    fn default() -> Self {
+        Self::new(&OutputSettings::default())
+    }
+}
+impl OutputLayout {
+    /// 設定から対応表を作成する関数
+    pub fn new(settings: &OutputSettings) -> Self {
+        Self {
+            panel: PanelOutput::ALL.iter()
+                .map(|output| resolve_index(&settings.panel, format!("{output:?}"), output.get_default_index()))
+                .collect(),
+            sound: SoundOutput::ALL.iter()
+                .map(|output| resolve_index(&settings.sound, format!("{output:?}"), output.get_default_index()))
+                .collect(),
+        }
+    }
+    /// パネルインデックスを取得する関数 (出力しない場合はNone)
+    pub fn get_panel_index(&self, output: PanelOutput) -> Option<usize> {
+        self.panel[output as usize]
+    }
+    /// サウンドインデックスを取得する関数 (出力しない場合はNone)
+    pub fn get_sound_index(&self, output: SoundOutput) -> Option<usize> {
+        self.sound[output as usize]
+    }
+    /// パネルに値を書き込む関数
+    pub fn set_panel(&self, panel: &mut [i32], output: PanelOutput, value: i32) {
+        if let Some(slot) = self.get_panel_index(output).and_then(|index| panel.get_mut(index)) {
+            *slot = value;
+        }
+    }
+    /// サウンドに値を書き込む関数
+    pub fn set_sound(&self, sound: &mut [i32], output: SoundOutput, value: i32) {
+        if let Some(slot) = self.get_sound_index(output).and_then(|index| sound.get_mut(index)) {
+            *slot = value;
+        }
+    }
+}
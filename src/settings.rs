@@ -1,39 +1,166 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use bveats_rs::{AtsKey, AtsVehicleSpec};
+use log::error;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Default)]
-#[derive(Serialize, Deserialize)]
-#[derive(Clone)]
-pub struct Settings {
-    #[serde(default)]
-    pub vehicle: VehicleSettings,
-    #[serde(default)]
-    pub atc: ATCSettings,
-    #[serde(default)]
-    pub ato: ATOSettings,
-    #[serde(default)]
-    pub tasc: TASCSettings,
-    #[serde(default)]
-    pub tims: TIMSSettings,
-    #[serde(default)]
-    pub sound: SoundSettings,
+use crate::atc::atc_signal::AtcSignal;
+use self::profile::SettingsFile;
+use self::schema::settings_struct;
+
+pub mod profile;
+pub mod schema;
+pub mod validation;
+
+settings_struct! {
+    @sections
+    #[derive(Debug, Default)]
+    #[derive(Serialize, Deserialize)]
+    #[derive(Clone)]
+    pub struct Settings {
+        /// 車両の設定
+        #[serde(default)]
+        pub vehicle: VehicleSettings,
+        /// ATCの設定
+        #[serde(default)]
+        pub atc: ATCSettings,
+        /// ATC注意喚起チャイムの設定
+        #[serde(default)]
+        pub caution: CautionSettings,
+        /// パターン式ATCの設定
+        #[serde(default)]
+        pub pattern: PatternSettings,
+        /// ATC開放スイッチの設定
+        #[serde(default)]
+        pub atc_release: AtcReleaseSettings,
+        /// 入換の設定
+        #[serde(default)]
+        pub irekae: IrekaeSettings,
+        /// 非設の設定
+        #[serde(default)]
+        pub hisetsu: HisetsuSettings,
+        /// ATOの設定
+        #[serde(default)]
+        pub ato: ATOSettings,
+        /// TASCの設定
+        #[serde(default)]
+        pub tasc: TASCSettings,
+        /// TIMSの設定
+        #[serde(default)]
+        pub tims: TIMSSettings,
+        /// サウンドの設定
+        #[serde(default)]
+        pub sound: SoundSettings,
+        /// 信号インデックスの設定
+        #[serde(default)]
+        pub signal: SignalSettings,
+        /// セッションの記録の設定
+        #[serde(default)]
+        pub record: RecordSettings,
+        /// 設定ファイルの再読み込みの設定
+        #[serde(default)]
+        pub reload: ReloadSettings,
+        /// キー割当ての設定
+        #[serde(default)]
+        pub keys: KeySettings,
+        /// パネル・サウンドの出力先の設定
+        #[serde(default)]
+        pub output: OutputSettings,
+        /// 戸閉保安の設定
+        #[serde(default)]
+        pub door: DoorSettings,
+        /// 勾配の設定
+        #[serde(default)]
+        pub gradient: GradientSettings,
+    }
+}
+impl Settings {
+    /// 設定ファイルを読み込む関数 (失敗した場合はデフォルト値を返す)
+    pub fn load(config_path: &Path) -> Settings {
+        match Self::try_load(config_path) {
+            Ok(settings) => settings,
+            Err(err) => {
+                error!("{err}");
+                Default::default()
+            },
+        }
+    }
+    /// 設定ファイルを読み込む関数 (失敗した場合はエラーを返す)
+    /// (プロファイルがある場合はデフォルトのプロファイルを反映する)
+    pub fn try_load(config_path: &Path) -> Result<Settings, String> {
+        let file = SettingsFile::load(config_path)?;
+        file.get_settings(file.default_profile.as_deref())
+    }
+    /// キーに割り当てられた動作を取得する関数
+    /// (`[keys]` の割当て → 各機能のキー設定 → 既定の割当て の順に優先する)
+    pub fn get_key_action(&self, key: AtsKey) -> KeyAction {
+        let key_name = format!("{key:?}");
+        if let Some(action) = self.keys.bindings.get(&key_name) {
+            return *action;
+        }
+        if key_name == self.atc_release.key {
+            KeyAction::AtcRelease
+        } else if key_name == self.atc_release.confirm_key {
+            KeyAction::AtcReleaseConfirm
+        } else if key_name == self.reload.key {
+            KeyAction::ReloadSettings
+        } else {
+            KeyAction::get_default(key)
+        }
+    }
+    /// 設定値が動作可能な範囲にあるかを確認する関数 (問題がある場合はレポートを返す)
+    pub fn validate(&self, spec: Option<&AtsVehicleSpec>) -> Result<(), String> {
+        let report = self.validate_report(spec);
+        if report.is_ok() {
+            Ok(())
+        } else {
+            Err(report.to_text())
+        }
+    }
 }
 
-#[derive(Debug)]
-#[derive(Serialize, Deserialize)]
-#[derive(Clone)]
-pub struct VehicleSettings {
-    /// 入力する力行の段数
-    pub input_power_notches: i32,
-    /// 入力するブレーキの段数
-    pub input_brake_notches: i32,
-    /// 出力する力行の段数
-    pub output_power_notches: i32,
-    /// 出力するブレーキの段数
-    pub output_brake_notches: i32,
-    /// 定速制御を開始する速度 [km/h]
-    pub constant_start_speed: f32,
-    /// 抑速制御を開始する速度 [km/h]
-    pub yokusoku_start_speed: f32,
+settings_struct! {
+    #[derive(Debug)]
+    #[derive(Serialize, Deserialize)]
+    #[derive(Clone)]
+    #[serde(default)]
+    pub struct VehicleSettings {
+        /// 入力する力行の段数
+        pub input_power_notches: i32,
+        /// 入力するブレーキの段数
+        pub input_brake_notches: i32,
+        /// 出力する力行の段数
+        pub output_power_notches: i32,
+        /// 出力するブレーキの段数
+        pub output_brake_notches: i32,
+        /// 定速制御を開始する速度 [km/h]
+        pub constant_start_speed: f32,
+        /// 抑速制御を開始する速度 [km/h]
+        pub yokusoku_start_speed: f32,
+        /// 定速制御の比例ゲイン [ノッチ/(km/h)]
+        pub constant_kp: f32,
+        /// 定速制御の積分ゲイン [ノッチ/(km/h・s)]
+        pub constant_ki: f32,
+        /// 定速制御の微分ゲイン [ノッチ/(km/h/s)]
+        pub constant_kd: f32,
+        /// 抑速制御の比例ゲイン [ノッチ/(km/h)]
+        pub holding_kp: f32,
+        /// 抑速制御の積分ゲイン [ノッチ/(km/h・s)]
+        pub holding_ki: f32,
+        /// 抑速制御の微分ゲイン [ノッチ/(km/h/s)]
+        pub holding_kd: f32,
+        /// 抑速1〜3ノッチの目標速度 (抑速投入時の速度からの減速量) [km/h]
+        pub holding_speed_offsets: Vec<f32>,
+        /// 抑速制御・抑速ノッチで出力する最大のブレーキノッチ (0の場合は出力するブレーキの段数の半分)
+        pub holding_max_brake_notch: i32,
+        /// 力行ノッチの対応表 (入力ノッチ0から順に出力ノッチを並べる, 空の場合は線形に変換する)
+        pub power_notch_map: Vec<i32>,
+        /// ブレーキノッチの対応表 (入力ノッチ0から順に出力ノッチを並べる, 空の場合は線形に変換する)
+        pub brake_notch_map: Vec<i32>,
+        /// 抑速ノッチの対応表 (抑速0から順に出力ブレーキノッチを並べる, 空の場合は線形に変換する)
+        pub holding_notch_map: Vec<i32>,
+    }
 }
 impl Default for VehicleSettings {
     fn default() -> Self {
@@ -43,23 +170,81 @@ impl Default for VehicleSettings {
             output_power_notches: 31, 
             output_brake_notches: 31, 
             constant_start_speed: 25.0, 
-            yokusoku_start_speed: 5.0
+            yokusoku_start_speed: 5.0,
+            constant_kp: 4.0,
+            constant_ki: 2.0,
+            constant_kd: 0.0,
+            holding_kp: 3.0,
+            holding_ki: 1.5,
+            holding_kd: 0.0,
+            holding_speed_offsets: vec![0.0, 5.0, 10.0],
+            holding_max_brake_notch: 0,
+            power_notch_map: Vec::new(),
+            brake_notch_map: Vec::new(),
+            holding_notch_map: Vec::new(),
+        }
+    }
+}
+impl VehicleSettings {
+    /// 対応表から入力ノッチを出力ノッチに変換する関数 (対応表が空の場合は線形に変換する)
+    fn map_notch(map: &[i32], notch: i32, input_notches: i32, output_notches: i32) -> i32 {
+        if map.is_empty() {
+            return notch * output_notches / input_notches.max(1);
+        }
+        map[notch.clamp(0, map.len() as i32 - 1) as usize]
+    }
+    /// 入力された力行ノッチを出力する力行ノッチに変換する関数
+    pub fn get_output_power_notch(&self, notch: i32) -> i32 {
+        Self::map_notch(&self.power_notch_map, notch, self.input_power_notches, self.output_power_notches)
+            .clamp(0, self.output_power_notches)
+    }
+    /// 入力されたブレーキノッチを出力するブレーキノッチに変換する関数 (非常ブレーキは出力の非常ブレーキとする)
+    pub fn get_output_brake_notch(&self, notch: i32) -> i32 {
+        if notch > self.input_brake_notches {
+            return self.output_brake_notches + 1;
+        }
+        Self::map_notch(&self.brake_notch_map, notch, self.input_brake_notches, self.output_brake_notches)
+            .clamp(0, self.output_brake_notches)
+    }
+    /// 入力された抑速ノッチ(正の値)を出力するブレーキノッチに変換する関数
+    pub fn get_output_holding_notch(&self, notch: i32) -> i32 {
+        Self::map_notch(&self.holding_notch_map, notch, self.input_brake_notches, self.output_brake_notches)
+            .clamp(0, self.get_holding_max_brake_notch())
+    }
+    /// 抑速で出力する最大のブレーキノッチを取得する関数
+    pub fn get_holding_max_brake_notch(&self) -> i32 {
+        if self.holding_max_brake_notch > 0 {
+            self.holding_max_brake_notch.min(self.output_brake_notches)
+        } else {
+            self.output_brake_notches / 2
+        }
+    }
+    /// ATC緩和ブレーキで出力するブレーキノッチを取得する関数
+    /// (対応表がある場合は入力ブレーキの中間のノッチを変換する)
+    pub fn get_half_brake_notch(&self) -> i32 {
+        if self.brake_notch_map.is_empty() {
+            self.output_brake_notches / 2
+        } else {
+            self.get_output_brake_notch((self.input_brake_notches + 1) / 2)
         }
     }
 }
 
-#[derive(Debug)]
-#[derive(Serialize, Deserialize)]
-#[derive(Clone)]
-pub struct ATCSettings {
-    /// ATC速度照査のマージン
-    pub check_speed_margin: f32,
-    /// 緩和ブレーキの長さ [ms]
-    pub half_brake_time: i32,
-    /// 確認運転時の照査速度 [km/h]
-    pub kakunin_limit_speed: f32,
-    /// 非常運転時の照査速度 [km/h]
-    pub hijo_limit_speed: f32,
+settings_struct! {
+    #[derive(Debug)]
+    #[derive(Serialize, Deserialize)]
+    #[derive(Clone)]
+    #[serde(default)]
+    pub struct ATCSettings {
+        /// ATC速度照査のマージン [km/h]
+        pub check_speed_margin: f32,
+        /// 緩和ブレーキの長さ [ms]
+        pub half_brake_time: i32,
+        /// 確認運転時の照査速度 [km/h]
+        pub kakunin_limit_speed: f32,
+        /// 非常運転時の照査速度 [km/h]
+        pub hijo_limit_speed: f32,
+    }
 }
 impl Default for ATCSettings {
     fn default() -> Self {
@@ -72,20 +257,172 @@ impl Default for ATCSettings {
     }
 }
 
-#[derive(Debug)]
+settings_struct! {
+    #[derive(Debug)]
+    #[derive(Serialize, Deserialize)]
+    #[derive(Clone)]
+    #[serde(default)]
+    pub struct CautionSettings {
+        /// 照査速度に接近した時に注意喚起チャイムを鳴動させるか
+        pub approach: bool,
+        /// 照査速度の何km/h手前から注意喚起するか [km/h]
+        pub approach_speed: f32,
+        /// 現示ダウン時に新しい信号速度を超えていた場合に注意喚起チャイムを鳴動させるか
+        pub downgrade: bool,
+        /// 注意喚起中に運転士がブレーキを操作しない場合に再度鳴動させるまでの時間 (0の場合は再鳴動しない) [ms]
+        pub response_timeout: i32,
+    }
+}
+impl Default for CautionSettings {
+    fn default() -> Self {
+        Self {
+            approach: false,
+            approach_speed: 3.0,
+            downgrade: false,
+            response_timeout: 5000,
+        }
+    }
+}
+
+settings_struct! {
+    #[derive(Debug)]
+    #[derive(Serialize, Deserialize)]
+    #[derive(Clone)]
+    #[serde(default)]
+    pub struct PatternSettings {
+        /// パターン式ATCを有効にするか
+        pub enable: bool,
+        /// パターンの減速度 [km/h/s]
+        pub deceleration: f32,
+        /// 目標地点の手前に確保する距離 [m]
+        pub margin_distance: f32,
+    }
+}
+impl Default for PatternSettings {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            deceleration: 2.5,
+            margin_distance: 10.0,
+        }
+    }
+}
+
+settings_struct! {
+    #[derive(Debug)]
+    #[derive(Serialize, Deserialize)]
+    #[derive(Clone)]
+    #[serde(default)]
+    pub struct AtcReleaseSettings {
+        /// ATC開放スイッチのキー (AtsKeyの名前)
+        pub key: String,
+        /// ATC開放スイッチを封印するか (封印時は確認キーの入力が必要)
+        pub seal: bool,
+        /// 封印解除の確認キー (AtsKeyの名前)
+        pub confirm_key: String,
+        /// 確認キーの入力を受け付ける時間 [ms]
+        pub confirm_time: i32,
+        /// ATC開放時の制限速度 [km/h]
+        pub limit_speed: f32,
+        /// ATC開放表示灯の点滅間隔 [ms]
+        pub blink_interval: i32,
+    }
+}
+impl Default for AtcReleaseSettings {
+    fn default() -> Self {
+        Self {
+            key: "F".to_string(),
+            seal: true,
+            confirm_key: "G".to_string(),
+            confirm_time: 3000,
+            limit_speed: 25.0,
+            blink_interval: 500,
+        }
+    }
+}
+
+settings_struct! {
+    #[derive(Debug)]
+    #[derive(Serialize, Deserialize)]
+    #[derive(Clone)]
+    #[serde(default)]
+    pub struct IrekaeSettings {
+        /// 入換速度照査のマージン [km/h]
+        pub check_speed_margin: f32,
+        /// 入換15信号の照査速度 [km/h]
+        pub signal15_speed: f32,
+        /// 入換25信号の照査速度 [km/h]
+        pub signal25_speed: f32,
+    }
+}
+impl Default for IrekaeSettings {
+    fn default() -> Self {
+        Self {
+            check_speed_margin: 1.5,
+            signal15_speed: 15.0,
+            signal25_speed: 25.0,
+        }
+    }
+}
+
+/// 非設時に切換要請となった場合のブレーキ種別
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
 #[derive(Serialize, Deserialize)]
-#[derive(Clone)]
-pub struct ATOSettings {
-    /// ATC速度とATO目標速度との差 [km/h]
-    pub target_speed: f32,
-    /// TASC第2パターン発生時の照査速度 [km/h]
-    pub p2_check_speed: f32,
-    /// 過速防止の照査速度 [km/h]
-    pub p3_check_speed: f32,
-    /// 減速制御時の最大減速時間 [ms]
-    pub p4_brake_time: i32,
-    /// 力行OFF制御時の最低条件速度 [km/h]
-    pub p5_lower_limit_speed: f32,
+pub enum HisetsuBrake {
+    /// ブレーキなし (切換要請ブザーのみ)
+    None,
+    /// 緩和ブレーキ
+    Half,
+    /// 常用最大ブレーキ
+    #[default]
+    Full,
+    /// 非常ブレーキ
+    Emergency,
+}
+
+settings_struct! {
+    #[derive(Debug)]
+    #[derive(Serialize, Deserialize)]
+    #[derive(Clone)]
+    #[serde(default)]
+    pub struct HisetsuSettings {
+        /// 非設時にATC信号を受信した場合のブレーキ種別 (None, Half, Full, Emergency)
+        pub brake: HisetsuBrake,
+    }
+}
+impl Default for HisetsuSettings {
+    fn default() -> Self {
+        Self {
+            brake: HisetsuBrake::Full,
+        }
+    }
+}
+
+settings_struct! {
+    #[derive(Debug)]
+    #[derive(Serialize, Deserialize)]
+    #[derive(Clone)]
+    #[serde(default)]
+    pub struct ATOSettings {
+        /// ATC速度とATO目標速度との差 [km/h]
+        pub target_speed: f32,
+        /// TASC第2パターン発生時の照査速度 [km/h]
+        pub p2_check_speed: f32,
+        /// 過速防止の照査速度 [km/h]
+        pub p3_check_speed: f32,
+        /// 減速制御時の最大減速時間 [ms]
+        pub p4_brake_time: i32,
+        /// 力行OFF制御時の最低条件速度 [km/h]
+        pub p5_lower_limit_speed: f32,
+        /// 出力する力行ノッチを1秒間に変化させる最大の段数 (0の場合は制限しない) [ノッチ/s]
+        pub power_notch_rate: f32,
+        /// 出力するブレーキノッチを1秒間に変化させる最大の段数 (0の場合は制限しない, 非常ブレーキは制限しない) [ノッチ/s]
+        pub brake_notch_rate: f32,
+        /// 力行を切ってからブレーキを投入するまでの最小の時間 (変化の段数と時間が全て0の場合は整形しない) [ms]
+        pub power_off_delay: i32,
+        /// ブレーキを投入してから緩解して力行するまでの最小の保持時間 (変化の段数と時間が全て0の場合は整形しない) [ms]
+        pub brake_hold_time: i32,
+    }
 }
 impl Default for ATOSettings {
     fn default() -> Self {
@@ -94,30 +431,347 @@ impl Default for ATOSettings {
             p2_check_speed: 25.0,
             p3_check_speed: 5.0,
             p4_brake_time: 8000,
-            p5_lower_limit_speed: 35.0
+            p5_lower_limit_speed: 35.0,
+            power_notch_rate: 0.0,
+            brake_notch_rate: 0.0,
+            power_off_delay: 0,
+            brake_hold_time: 0,
         }
     }
 }
 
-#[derive(Debug, Default)]
-#[derive(Serialize, Deserialize)]
-#[derive(Clone)]
-pub struct TASCSettings {
-    /// 在来車のTASCパターンか
-    pub is_old_pattern: bool,
+settings_struct! {
+    #[derive(Debug)]
+    #[derive(Serialize, Deserialize)]
+    #[derive(Clone)]
+    #[serde(default)]
+    pub struct TASCSettings {
+        /// 在来車のTASCパターンか
+        pub is_old_pattern: bool,
+        /// 地上子のoptionalで停止目標までの距離を受け取る場合の単位 (0.01でcm単位, 既定の0では使用しない) [m]
+        pub beacon_distance_unit: f32,
+        /// 第1パターン地上子から停止目標までの距離 (地上子・駅ごとの設定がない場合) [m]
+        pub p1_distance: f32,
+        /// 第2パターン地上子から停止目標までの距離 (地上子・駅ごとの設定がない場合) [m]
+        pub p2_distance: f32,
+        /// 90パターン地上子から停止目標までの距離 (地上子・駅ごとの設定がない場合) [m]
+        pub p90_distance: f32,
+        /// 駅ごとの停止目標までの距離 (駅番号をキーにする, 地上子の距離より優先する)
+        pub stations: BTreeMap<String, TascStationSettings>,
+        /// 停止精度のレポートを設定ファイルと同じフォルダに出力するか
+        pub report: bool,
+        /// 最大のブレーキノッチで得られる減速度 (学習前のブレーキノッチの選択に使用する) [km/h/s]
+        pub max_deceleration: f32,
+        /// ブレーキノッチごとの減速度を学習し、ブレーキノッチの選択に使用するか
+        pub adaptive_brake: bool,
+        /// 減速度の学習率 (1回の測定値を反映する割合)
+        pub learning_rate: f32,
+        /// ブレーキノッチが変化してから減速度を測定し始めるまでの時間 [ms]
+        pub learning_settle_time: i32,
+        /// 減速度を測定する最低速度 [km/h]
+        pub learning_min_speed: f32,
+    }
+}
+impl Default for TASCSettings {
+    fn default() -> Self {
+        Self {
+            is_old_pattern: false,
+            beacon_distance_unit: 0.0,
+            p1_distance: 350.5,
+            p2_distance: 25.5,
+            p90_distance: 600.5,
+            stations: BTreeMap::new(),
+            report: false,
+            max_deceleration: 3.50,
+            adaptive_brake: false,
+            learning_rate: 0.1,
+            learning_settle_time: 1000,
+            learning_min_speed: 5.0,
+        }
+    }
+}
+impl TASCSettings {
+    /// 停止目標までの距離を取得する関数 (駅ごとの設定 → 地上子のoptional → 既定値 の順に優先する)
+    pub fn get_target_distance(&self, pattern: TascPattern, station: Option<i32>, optional: i32) -> f32 {
+        let station_distance = station
+            .and_then(|station| self.stations.get(&station.to_string()))
+            .and_then(|station| match pattern {
+                TascPattern::P1 => station.p1_distance,
+                TascPattern::P2 => station.p2_distance,
+                TascPattern::P90 => station.p90_distance,
+            });
+        if let Some(distance) = station_distance {
+            return distance;
+        }
+        if optional > 0 && self.beacon_distance_unit > 0.0 {
+            return optional as f32 * self.beacon_distance_unit;
+        }
+        match pattern {
+            TascPattern::P1 => self.p1_distance,
+            TascPattern::P2 => self.p2_distance,
+            TascPattern::P90 => self.p90_distance,
+        }
+    }
 }
 
-#[derive(Debug, Default)]
-#[derive(Serialize, Deserialize)]
-#[derive(Clone)]
-pub struct TIMSSettings {
-    /// TIMS画面の描画速度
-    pub display_draw_time: i32,
+/// TASCのパターン地上子の種類
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TascPattern {
+    /// 第1パターン
+    P1,
+    /// 第2パターン
+    P2,
+    /// 90パターン
+    P90,
 }
 
-#[derive(Debug, Default)]
+settings_struct! {
+    #[derive(Debug, Default)]
+    #[derive(Serialize, Deserialize)]
+    #[derive(Clone)]
+    pub struct TascStationSettings {
+        /// 第1パターン地上子から停止目標までの距離 [m]
+        pub p1_distance: Option<f32>,
+        /// 第2パターン地上子から停止目標までの距離 [m]
+        pub p2_distance: Option<f32>,
+        /// 90パターン地上子から停止目標までの距離 [m]
+        pub p90_distance: Option<f32>,
+    }
+}
+
+settings_struct! {
+    #[derive(Debug, Default)]
+    #[derive(Serialize, Deserialize)]
+    #[derive(Clone)]
+    #[serde(default)]
+    pub struct TIMSSettings {
+        /// TIMS画面の描画速度 [ms]
+        pub display_draw_time: i32,
+    }
+}
+
+settings_struct! {
+    #[derive(Debug, Default)]
+    #[derive(Serialize, Deserialize)]
+    #[derive(Clone)]
+    #[serde(default)]
+    pub struct SoundSettings {
+
+    }
+}
+
+settings_struct! {
+    #[derive(Debug)]
+    #[derive(Serialize, Deserialize)]
+    #[derive(Clone)]
+    #[serde(default)]
+    pub struct SignalSettings {
+        /// 信号インデックスとATC信号の対応
+        pub index: BTreeMap<String, AtcSignal>,
+        /// 対応のない信号インデックスを受信した時に現示するATC信号
+        pub unknown_signal: AtcSignal,
+        /// 対応のない信号インデックスを無視するか
+        pub ignore_unknown: bool,
+    }
+}
+impl Default for SignalSettings {
+    fn default() -> Self {
+        let signals = [
+            AtcSignal::Signal02,
+            AtcSignal::Signal01,
+            AtcSignal::Signal15,
+            AtcSignal::Signal25,
+            AtcSignal::Signal45,
+            AtcSignal::Signal60,
+            AtcSignal::Signal75,
+            AtcSignal::Signal90,
+            AtcSignal::Irekae15,
+            AtcSignal::Irekae25,
+        ];
+        Self {
+            index: signals.iter().enumerate().map(|(i, signal)| (i.to_string(), *signal)).collect(),
+            unknown_signal: AtcSignal::Signal02,
+            ignore_unknown: false,
+        }
+    }
+}
+impl SignalSettings {
+    /// 信号インデックスに対応するATC信号を取得する関数 (無視する場合はNone)
+    pub fn get_signal(&self, index: i32) -> Option<AtcSignal> {
+        match self.index.get(&index.to_string()) {
+            Some(signal) => Some(*signal),
+            None if self.ignore_unknown => None,
+            None => Some(self.unknown_signal),
+        }
+    }
+}
+
+settings_struct! {
+    #[derive(Debug, Default)]
+    #[derive(Serialize, Deserialize)]
+    #[derive(Clone)]
+    #[serde(default)]
+    pub struct RecordSettings {
+        /// BVEから呼び出された関数をDLLと同じフォルダに記録するか
+        pub enable: bool,
+    }
+}
+
+settings_struct! {
+    #[derive(Debug)]
+    #[derive(Serialize, Deserialize)]
+    #[derive(Clone)]
+    #[serde(default)]
+    pub struct ReloadSettings {
+        /// 設定ファイルを再読み込みするキー (AtsKeyの名前, 空の場合は無効)
+        pub key: String,
+        /// Initialize時に設定ファイルの更新を検知して再読み込みするか
+        pub on_initialize: bool,
+    }
+}
+impl Default for ReloadSettings {
+    fn default() -> Self {
+        Self {
+            key: "A2".to_string(),
+            on_initialize: true,
+        }
+    }
+}
+
+/// キーに割り当てる動作
+#[derive(Debug, Clone, Copy, PartialEq)]
 #[derive(Serialize, Deserialize)]
-#[derive(Clone)]
-pub struct SoundSettings {
+pub enum KeyAction {
+    /// 割当てなし (他のプラグインと併用する場合など)
+    None,
+    /// ATO出発スイッチ
+    AtoDeparture,
+    /// 運転切換スイッチ 左回転
+    ModeLeft,
+    /// 運転切換スイッチ 右回転
+    ModeRight,
+    /// 非常運転
+    HijoUnten,
+    /// 確認運転
+    KakuninUnten,
+    /// ATC開放スイッチ
+    AtcRelease,
+    /// ATC開放スイッチ 封印解除の確認
+    AtcReleaseConfirm,
+    /// 設定ファイルの再読み込み
+    ReloadSettings,
+    /// 緊急放送 信号待ち
+    EmgSignalWait,
+    /// 緊急放送 急病人対応
+    EmgEmergencyCase,
+    /// 緊急放送 緊急停止
+    EmgEmergencyStop,
+    /// 緊急放送 シート交換
+    EmgSeatExchange,
+    /// 緊急放送 非常ブレーキ
+    EmgEmergencyBrake,
+}
+impl KeyAction {
+    /// すべての動作 (uline-config で出力する)
+    pub const ALL: [KeyAction; 14] = [
+        KeyAction::None,
+        KeyAction::AtoDeparture,
+        KeyAction::ModeLeft,
+        KeyAction::ModeRight,
+        KeyAction::HijoUnten,
+        KeyAction::KakuninUnten,
+        KeyAction::AtcRelease,
+        KeyAction::AtcReleaseConfirm,
+        KeyAction::ReloadSettings,
+        KeyAction::EmgSignalWait,
+        KeyAction::EmgEmergencyCase,
+        KeyAction::EmgEmergencyStop,
+        KeyAction::EmgSeatExchange,
+        KeyAction::EmgEmergencyBrake,
+    ];
+    /// 既定のキー割当て (ATC開放と再読み込みは各機能のキー設定による)
+    pub fn get_default(key: AtsKey) -> KeyAction {
+        match key {
+            AtsKey::S => KeyAction::AtoDeparture,
+            AtsKey::C1 => KeyAction::ModeLeft,
+            AtsKey::C2 => KeyAction::ModeRight,
+            AtsKey::D => KeyAction::HijoUnten,
+            AtsKey::E => KeyAction::KakuninUnten,
+            AtsKey::H => KeyAction::EmgSignalWait,
+            AtsKey::I => KeyAction::EmgEmergencyCase,
+            AtsKey::J => KeyAction::EmgEmergencyStop,
+            AtsKey::K => KeyAction::EmgSeatExchange,
+            AtsKey::L => KeyAction::EmgEmergencyBrake,
+            _ => KeyAction::None,
+        }
+    }
+    /// 緊急放送の動作か
+    pub fn is_emg_sound(&self) -> bool {
+        matches!(self,
+            KeyAction::EmgSignalWait | KeyAction::EmgEmergencyCase | KeyAction::EmgEmergencyStop
+            | KeyAction::EmgSeatExchange | KeyAction::EmgEmergencyBrake)
+    }
+}
+
+settings_struct! {
+    #[derive(Debug, Default)]
+    #[derive(Serialize, Deserialize)]
+    #[derive(Clone)]
+    pub struct KeySettings {
+        /// AtsKeyの名前と動作の対応 (例: S = "AtoDeparture", "None" で割当てなし。記述のないキーは既定の割当て)
+        #[serde(flatten)]
+        pub bindings: BTreeMap<String, KeyAction>,
+    }
+}
+
+settings_struct! {
+    #[derive(Debug, Default)]
+    #[derive(Serialize, Deserialize)]
+    #[derive(Clone)]
+    #[serde(default)]
+    pub struct OutputSettings {
+        /// パネルの出力名とインデックスの対応 (例: { Signal02 = 131 }, -1で出力しない。記述のない出力は既定のインデックス)
+        pub panel: BTreeMap<String, i32>,
+        /// サウンドの出力名とインデックスの対応 (例: { AtcBell = 12 }, -1で出力しない。記述のない出力は既定のインデックス)
+        pub sound: BTreeMap<String, i32>,
+    }
+}
+
+settings_struct! {
+    #[derive(Debug, Default)]
+    #[derive(Serialize, Deserialize)]
+    #[derive(Clone)]
+    #[serde(default)]
+    pub struct DoorSettings {
+        /// 戸閉保安を使用するか (ドアが開いている間は力行できず、ATO出発もできない, 既定では使用しない)
+        pub enable: bool,
+        /// ドアが閉まってから戸閉保安を解除するまでの時間 [ms]
+        pub release_delay: i32,
+        /// 戸閉保安の作動中に保持するブレーキ (入力ブレーキノッチ, 0の場合は保持しない)
+        pub brake_notch: i32,
+    }
+}
 
-}
\ No newline at end of file
+settings_struct! {
+    #[derive(Debug)]
+    #[derive(Serialize, Deserialize)]
+    #[derive(Clone)]
+    #[serde(default)]
+    pub struct GradientSettings {
+        /// 勾配地上子で受信した勾配を打ち消すノッチをATO・TASC・定速制御・抑速制御に加えるか
+        pub enable: bool,
+        /// 勾配を打ち消すノッチにかける係数
+        pub feed_forward_gain: f32,
+        /// 最大の力行ノッチで得られる加速度 (勾配を打ち消す力行ノッチの計算に使用する) [km/h/s]
+        pub max_acceleration: f32,
+    }
+}
+impl Default for GradientSettings {
+    fn default() -> Self {
+        Self {
+            enable: true,
+            feed_forward_gain: 1.0,
+            max_acceleration: 3.0,
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! 車両プロファイル
+//!
+//! 1つの設定ファイルに複数の車両の設定を記述し、車両諸元や地上子で切り替える。
+//! プロファイルには基本の設定から変更する項目だけを記述する。
+//!
+//! ```toml
+//! default_profile = "3000"
+//!
+//! [vehicle]
+//! input_brake_notches = 7
+//!
+//! [[profile]]
+//! name = "3000"
+//! brake_notches = 7
+//!
+//! [[profile]]
+//! name = "6000"
+//! brake_notches = 8
+//! beacon = 2
+//! [profile.vehicle]
+//! input_brake_notches = 8
+//! ```
+
+use std::path::Path;
+
+use bveats_rs::AtsVehicleSpec;
+use serde::{Deserialize, Serialize};
+
+use super::schema::{settings_struct, FieldDoc};
+use super::Settings;
+
+settings_struct! {
+    /// 車両プロファイル
+    #[derive(Debug)]
+    #[derive(Serialize, Deserialize)]
+    #[derive(Clone)]
+    pub struct VehicleProfile {
+        /// プロファイル名
+        pub name: String,
+        /// 選択条件: ブレーキノッチ数 (省略時は条件にしない)
+        #[serde(default)]
+        pub brake_notches: Option<i32>,
+        /// 選択条件: 力行ノッチ数 (省略時は条件にしない)
+        #[serde(default)]
+        pub power_notches: Option<i32>,
+        /// 選択条件: 編成両数 (省略時は条件にしない)
+        #[serde(default)]
+        pub cars: Option<i32>,
+        /// 地上子(種別22)で選択する番号
+        #[serde(default)]
+        pub beacon: Option<i32>,
+        /// 基本の設定から変更する項目
+        #[serde(flatten)]
+        pub overrides: toml::Table,
+    }
+}
+
+impl VehicleProfile {
+    /// 車両諸元が選択条件に一致するか (条件がない場合は一致しない)
+    pub fn matches(&self, spec: &AtsVehicleSpec) -> bool {
+        let conditions = [
+            (self.brake_notches, spec.brake_notches),
+            (self.power_notches, spec.power_notches),
+            (self.cars, spec.cars),
+        ];
+        conditions.iter().any(|(condition, _)| condition.is_some())
+            && conditions.iter().all(|(condition, value)| condition.is_none_or(|condition| condition == *value))
+    }
+}
+
+/// 設定ファイル全体 (基本の設定とプロファイル)
+#[derive(Debug, Default)]
+#[derive(Clone)]
+pub struct SettingsFile {
+    /// 基本の設定 (プロファイル以外の項目)
+    base: toml::Table,
+    /// 条件に一致するプロファイルがない場合に使用するプロファイル名
+    pub default_profile: Option<String>,
+    /// 車両プロファイル
+    pub profiles: Vec<VehicleProfile>,
+}
+
+/// テーブルに別のテーブルの項目を上書きする関数 (テーブル同士は項目ごとに上書きする)
+fn merge_table(base: &mut toml::Table, overrides: &toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overrides)) => merge_table(base, overrides),
+            _ => { base.insert(key.clone(), value.clone()); },
+        }
+    }
+}
+
+impl SettingsFile {
+    /// 設定ファイルの最上位の項目名と説明 (基本の設定のテーブルを除く)
+    pub const FIELDS: &'static [FieldDoc] = &[
+        FieldDoc { name: "default_profile", doc: "条件に一致するプロファイルがない場合に使用するプロファイル名", serde: "" },
+        FieldDoc { name: "profile", doc: "車両プロファイル (基本の設定から変更する項目だけを記述する)", serde: "" },
+    ];
+    /// 設定ファイルの内容を解析する関数
+    pub fn parse(text: &str) -> Result<SettingsFile, String> {
+        let mut base: toml::Table = toml::from_str(text).map_err(|err| format!("設定ファイルのパースに失敗しました。 {err}"))?;
+        let default_profile = match base.remove("default_profile") {
+            Some(toml::Value::String(name)) => Some(name),
+            Some(value) => return Err(format!("default_profile にはプロファイル名を指定してください。({value})")),
+            None => None,
+        };
+        let profiles = match base.remove("profile") {
+            Some(profiles) => profiles.try_into::<Vec<VehicleProfile>>()
+                .map_err(|err| format!("プロファイルのパースに失敗しました。 {err}"))?,
+            None => Vec::new(),
+        };
+        let file = SettingsFile { base, default_profile, profiles };
+        // 基本の設定と各プロファイルが設定として読み込めるかを先に確認する
+        file.get_settings(None)?;
+        for profile in &file.profiles {
+            file.get_settings(Some(&profile.name))?;
+        }
+        if let Some(name) = &file.default_profile {
+            if file.get_profile(name).is_none() {
+                return Err(format!("default_profile のプロファイルがありません。({name})"));
+            }
+        }
+        Ok(file)
+    }
+    /// 設定ファイルを読み込む関数 (失敗した場合はエラーを返す)
+    pub fn load(config_path: &Path) -> Result<SettingsFile, String> {
+        let config_data = std::fs::read_to_string(config_path)
+            .map_err(|_| format!("設定ファイルの読み込みに失敗しました。({:?})", config_path))?;
+        Self::parse(&config_data)
+    }
+    /// 名前からプロファイルを取得する関数
+    pub fn get_profile(&self, name: &str) -> Option<&VehicleProfile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+    /// 車両諸元からプロファイルを選択する関数 (一致しない場合はデフォルトのプロファイル)
+    pub fn select_by_spec(&self, spec: &AtsVehicleSpec) -> Option<&VehicleProfile> {
+        self.profiles.iter()
+            .find(|profile| profile.matches(spec))
+            .or_else(|| self.default_profile.as_deref().and_then(|name| self.get_profile(name)))
+    }
+    /// 地上子の番号からプロファイルを選択する関数
+    pub fn select_by_beacon(&self, number: i32) -> Option<&VehicleProfile> {
+        self.profiles.iter().find(|profile| profile.beacon == Some(number))
+    }
+    /// プロファイルを反映した設定を取得する関数 (`None` の場合は基本の設定)
+    pub fn get_settings(&self, profile: Option<&str>) -> Result<Settings, String> {
+        let mut table = self.base.clone();
+        if let Some(name) = profile {
+            let profile = self.get_profile(name).ok_or(format!("プロファイルがありません。({name})"))?;
+            merge_table(&mut table, &profile.overrides);
+        }
+        toml::Value::Table(table).try_into()
+            .map_err(|err| match profile {
+                Some(name) => format!("プロファイル {name} の設定のパースに失敗しました。 {err}"),
+                None => format!("設定ファイルのパースに失敗しました。 {err}"),
+            })
+    }
+}
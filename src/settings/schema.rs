@@ -0,0 +1,502 @@
+//! 設定ファイルのスキーマとコメント付きのデフォルト設定ファイルの生成
+//!
+//! 項目の説明は `settings_struct!` で定義した構造体のドキュメントコメントから、デフォルト値と型は `Settings::default()` から取得する。
+//! (説明の末尾の `[km/h]` などを単位とする)
+
+use bveats_rs::AtsKey;
+
+use super::profile::{SettingsFile, VehicleProfile};
+use super::{KeyAction, Settings, TascStationSettings};
+use crate::output::{PanelOutput, SoundOutput};
+
+/// 設定の構造体のフィールドの説明 (`settings_struct!` でドキュメントコメントから生成する)
+#[derive(Debug, Clone, Copy)]
+pub struct FieldDoc {
+    /// 項目名
+    pub name: &'static str,
+    /// ドキュメントコメント (末尾の `[km/h]` などは単位)
+    pub doc: &'static str,
+    /// フィールドの `#[serde(...)]` の内容
+    pub serde: &'static str,
+}
+
+impl FieldDoc {
+    /// テーブルの項目を展開するか (`#[serde(flatten)]`)
+    pub fn is_flatten(&self) -> bool {
+        self.serde.split(|c: char| !c.is_alphanumeric() && c != '_').any(|word| word == "flatten")
+    }
+}
+
+/// 設定ファイルのテーブルの項目の説明
+pub trait SettingsDoc {
+    /// フィールドの説明 (フィールドの順)
+    const FIELDS: &'static [FieldDoc];
+}
+
+/// 設定の構造体を定義し、フィールドのドキュメントコメントから `SettingsDoc` を実装するマクロ
+///
+/// フィールドにはドキュメントコメントの後に `#[serde(...)]` だけを記述できる。
+/// `@sections` を付けた構造体 (`Settings`) は、各フィールドの型の説明をまとめた `SECTIONS` も生成する。
+macro_rules! settings_struct {
+    (
+        @sections
+        $(#[$attr:meta])*
+        pub struct $name:ident {
+            $(
+                $(#[doc = $doc:literal])*
+                $(#[serde($($serde:tt)*)])*
+                pub $field:ident: $ty:ty
+            ),* $(,)?
+        }
+    ) => {
+        settings_struct! {
+            $(#[$attr])*
+            pub struct $name {
+                $(
+                    $(#[doc = $doc])*
+                    $(#[serde($($serde)*)])*
+                    pub $field: $ty,
+                )*
+            }
+        }
+        impl $name {
+            /// 設定ファイルのテーブルの説明と項目の説明 (フィールドの順)
+            pub const SECTIONS: &'static [($crate::settings::schema::FieldDoc, &'static [$crate::settings::schema::FieldDoc])] = &[
+                $((
+                    $crate::settings::schema::FieldDoc {
+                        name: stringify!($field),
+                        doc: concat!($($doc),*),
+                        serde: stringify!($($($serde)*)*),
+                    },
+                    <$ty as $crate::settings::schema::SettingsDoc>::FIELDS,
+                ),)*
+            ];
+        }
+    };
+    (
+        $(#[$attr:meta])*
+        pub struct $name:ident {
+            $(
+                $(#[doc = $doc:literal])*
+                $(#[serde($($serde:tt)*)])*
+                pub $field:ident: $ty:ty
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$attr])*
+        pub struct $name {
+            $(
+                $(#[doc = $doc])*
+                $(#[serde($($serde)*)])*
+                pub $field: $ty,
+            )*
+        }
+        impl $crate::settings::schema::SettingsDoc for $name {
+            const FIELDS: &'static [$crate::settings::schema::FieldDoc] = &[
+                $($crate::settings::schema::FieldDoc {
+                    name: stringify!($field),
+                    doc: concat!($($doc),*),
+                    serde: stringify!($($($serde)*)*),
+                },)*
+            ];
+        }
+    };
+}
+pub(crate) use settings_struct;
+
+/// 設定項目のスキーマ
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct FieldSchema {
+    /// 項目名
+    pub name: String,
+    /// TOMLの型 (integer, float, boolean, string, array, table)
+    pub value_type: String,
+    /// 説明
+    pub description: String,
+    /// 単位
+    pub unit: Option<String>,
+    /// デフォルト値
+    pub default: Option<toml::Value>,
+    /// テーブルの項目を展開するか (`#[serde(flatten)]`, 名前の一覧で説明する)
+    pub flatten: bool,
+}
+
+/// 設定テーブルに記述できる名前の一覧 (キーの名前やパネルの出力名など)
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct NameSchema {
+    /// 一覧の名前
+    pub name: String,
+    /// 説明
+    pub description: String,
+    /// 名前を記述する項目 (`[output]` の `panel` など, テーブルに直接記述する場合はNone)
+    pub field: Option<String>,
+    /// 名前と既定値 (値のない一覧の場合はNone)
+    pub items: Vec<(String, Option<toml::Value>)>,
+}
+
+/// 設定テーブルのスキーマ
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct SectionSchema {
+    /// テーブル名
+    pub name: String,
+    /// 説明
+    pub description: String,
+    /// 項目
+    pub fields: Vec<FieldSchema>,
+    /// 記述できる名前の一覧
+    pub names: Vec<NameSchema>,
+    /// 項目に記述するテーブル (`[tasc.stations.<駅番号>]` など, テーブル名は項目名, 項目のデフォルト値は記述例)
+    pub tables: Vec<SectionSchema>,
+}
+
+/// 説明の末尾にある単位を分離する関数
+fn split_unit(description: &str) -> (String, Option<String>) {
+    match description.strip_suffix(']').and_then(|rest| rest.rsplit_once('[')) {
+        Some((description, unit)) => (description.trim().to_string(), Some(unit.to_string())),
+        None => (description.to_string(), None),
+    }
+}
+
+/// f32の値をf64で表した時の誤差を取り除く関数 (0.009999999776482582 → 0.01)
+fn round_float(value: toml::Value) -> toml::Value {
+    match value {
+        toml::Value::Float(number) => toml::Value::Float((number as f32).to_string().parse().unwrap_or(number)),
+        toml::Value::Array(array) => toml::Value::Array(array.into_iter().map(round_float).collect()),
+        toml::Value::Table(table) => toml::Value::Table(table.into_iter().map(|(key, value)| (key, round_float(value))).collect()),
+        value => value,
+    }
+}
+
+/// 項目の説明と値のテーブルから項目のスキーマを取得する関数
+fn get_fields(docs: &[FieldDoc], values: Option<&toml::Value>) -> Vec<FieldSchema> {
+    docs.iter()
+        .map(|doc| {
+            let (description, unit) = split_unit(doc.doc.trim());
+            let flatten = doc.is_flatten();
+            let default = match flatten {
+                true => None,
+                false => values.and_then(|table| table.get(doc.name)).cloned().map(round_float),
+            };
+            let value_type = match flatten {
+                true => "table",
+                false => default.as_ref().map_or("", |value| value.type_str()),
+            };
+            FieldSchema { name: doc.name.to_string(), value_type: value_type.to_string(), description, unit, default, flatten }
+        })
+        .collect()
+}
+
+/// 設定テーブルに記述できる名前の一覧を取得する関数
+fn get_names(section: &str, settings: &Settings) -> Vec<NameSchema> {
+    let name_list = |name: &str, description: &str, field: Option<&str>, items| NameSchema {
+        name: name.to_string(),
+        description: description.to_string(),
+        field: field.map(str::to_string),
+        items,
+    };
+    match section {
+        "keys" => vec![
+            name_list("keys", "キーの名前と既定の割当て", None, (0..=15)
+                .map(AtsKey::from)
+                .map(|key| (format!("{key:?}"), Some(toml::Value::String(format!("{:?}", settings.get_key_action(key))))))
+                .collect()),
+            name_list("actions", "割り当てられる動作", None, KeyAction::ALL
+                .iter()
+                .map(|action| (format!("{action:?}"), None))
+                .collect()),
+        ],
+        "output" => vec![
+            name_list("panel", "パネルの出力名と既定のインデックス", Some("panel"), PanelOutput::ALL
+                .iter()
+                .map(|output| (format!("{output:?}"), Some(toml::Value::Integer(output.get_default_index() as i64))))
+                .collect()),
+            name_list("sound", "サウンドの出力名と既定のインデックス", Some("sound"), SoundOutput::ALL
+                .iter()
+                .map(|output| (format!("{output:?}"), Some(toml::Value::Integer(output.get_default_index() as i64))))
+                .collect()),
+        ],
+        _ => Vec::new(),
+    }
+}
+
+/// 設定テーブルの項目に記述するテーブルを取得する関数 (値は記述例)
+fn get_tables(section: &str, fields: &[FieldSchema], settings: &Settings) -> Vec<SectionSchema> {
+    let table = |name: &str, docs: &[FieldDoc], example: toml::Value| SectionSchema {
+        name: name.to_string(),
+        description: fields.iter().find(|field| field.name == name).map(|field| field.description.clone()).unwrap_or_default(),
+        fields: get_fields(docs, Some(&example)),
+        names: Vec::new(),
+        tables: Vec::new(),
+    };
+    match section {
+        "tasc" => {
+            let station = TascStationSettings {
+                p1_distance: Some(settings.tasc.p1_distance),
+                p2_distance: Some(settings.tasc.p2_distance),
+                p90_distance: Some(settings.tasc.p90_distance),
+            };
+            let example = toml::Value::try_from(station).unwrap_or(toml::Value::Table(Default::default()));
+            vec![table("stations", TascStationSettings::FIELDS, example)]
+        },
+        _ => Vec::new(),
+    }
+}
+
+/// 設定ファイルのスキーマを取得する関数
+pub fn get_schema() -> Vec<SectionSchema> {
+    let settings = Settings::default();
+    let defaults = toml::Value::try_from(&settings).unwrap_or(toml::Value::Table(Default::default()));
+    Settings::SECTIONS
+        .iter()
+        .map(|(section, docs)| {
+            let fields = get_fields(docs, defaults.get(section.name));
+            SectionSchema {
+                name: section.name.to_string(),
+                description: section.doc.trim().to_string(),
+                names: get_names(section.name, &settings),
+                tables: get_tables(section.name, &fields, &settings),
+                fields,
+            }
+        })
+        .collect()
+}
+
+/// 車両プロファイルの記述例
+fn get_example_profile() -> toml::Value {
+    let mut overrides = toml::Table::new();
+    overrides.insert("vehicle".to_string(), toml::Value::Table(toml::Table::from_iter([
+        ("input_brake_notches".to_string(), toml::Value::Integer(8)),
+    ])));
+    let profile = VehicleProfile {
+        name: "6000".to_string(),
+        brake_notches: Some(8),
+        power_notches: Some(5),
+        cars: Some(10),
+        beacon: Some(2),
+        overrides,
+    };
+    toml::Value::try_from(profile).unwrap_or(toml::Value::Table(Default::default()))
+}
+
+/// 設定ファイルの最上位の項目 (default_profile, profile) のスキーマを取得する関数
+pub fn get_file_schema() -> Vec<FieldSchema> {
+    let example = toml::Value::Table(toml::Table::from_iter([
+        ("default_profile".to_string(), toml::Value::String("6000".to_string())),
+        ("profile".to_string(), toml::Value::Array(vec![get_example_profile()])),
+    ]));
+    get_fields(SettingsFile::FIELDS, Some(&example))
+        .into_iter()
+        .map(|field| FieldSchema { default: None, ..field })
+        .collect()
+}
+
+/// 車両プロファイルの項目のスキーマを取得する関数 (基本の設定から変更する項目を除く)
+pub fn get_profile_schema() -> Vec<FieldSchema> {
+    get_fields(VehicleProfile::FIELDS, Some(&get_example_profile()))
+        .into_iter()
+        .filter(|field| !field.flatten)
+        .map(|field| FieldSchema { default: None, ..field })
+        .collect()
+}
+
+/// JSONの文字列としてエスケープする関数
+fn json_string(text: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// TOMLの値をJSONに変換する関数
+fn json_value(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(text) => json_string(text),
+        toml::Value::Integer(number) => number.to_string(),
+        toml::Value::Float(number) => number.to_string(),
+        toml::Value::Boolean(flag) => flag.to_string(),
+        toml::Value::Datetime(datetime) => json_string(&datetime.to_string()),
+        toml::Value::Array(array) => format!("[{}]", array.iter().map(json_value).collect::<Vec<_>>().join(", ")),
+        toml::Value::Table(table) => format!(
+            "{{{}}}",
+            table.iter().map(|(key, value)| format!("{}: {}", json_string(key), json_value(value))).collect::<Vec<_>>().join(", ")
+        ),
+    }
+}
+
+/// 項目のスキーマをJSONの配列の要素として出力する関数
+fn json_fields(fields: &[FieldSchema], indent: &str) -> String {
+    fields
+        .iter()
+        .map(|field| format!(
+            "{indent}{{\"name\": {}, \"type\": {}, \"description\": {}, \"unit\": {}, \"default\": {}, \"flatten\": {}}}",
+            json_string(&field.name),
+            json_string(&field.value_type),
+            json_string(&field.description),
+            field.unit.as_deref().map(json_string).unwrap_or("null".to_string()),
+            field.default.as_ref().map(json_value).unwrap_or("null".to_string()),
+            field.flatten,
+        ))
+        .collect::<Vec<_>>()
+        .join(",\n")
+}
+
+/// 名前の一覧をJSONの配列の要素として出力する関数
+fn json_names(names: &[NameSchema]) -> String {
+    names
+        .iter()
+        .map(|names| format!(
+            "        {{\"name\": {}, \"description\": {}, \"field\": {}, \"items\": [{}]}}",
+            json_string(&names.name),
+            json_string(&names.description),
+            names.field.as_deref().map(json_string).unwrap_or("null".to_string()),
+            names.items
+                .iter()
+                .map(|(name, value)| format!(
+                    "{{\"name\": {}, \"default\": {}}}",
+                    json_string(name),
+                    value.as_ref().map(json_value).unwrap_or("null".to_string()),
+                ))
+                .collect::<Vec<_>>()
+                .join(", "),
+        ))
+        .collect::<Vec<_>>()
+        .join(",\n")
+}
+
+/// 項目に記述するテーブルをJSONの配列の要素として出力する関数
+fn json_tables(tables: &[SectionSchema]) -> String {
+    tables
+        .iter()
+        .map(|table| format!(
+            "        {{\"name\": {}, \"description\": {}, \"fields\": [\n{}\n        ]}}",
+            json_string(&table.name),
+            json_string(&table.description),
+            json_fields(&table.fields, "          "),
+        ))
+        .collect::<Vec<_>>()
+        .join(",\n")
+}
+
+/// 設定ファイルのスキーマをJSONで出力する関数
+pub fn to_json_schema() -> String {
+    let sections: Vec<String> = get_schema()
+        .iter()
+        .map(|section| format!(
+            "    {{\n      \"name\": {},\n      \"description\": {},\n      \"fields\": [\n{}\n      ],\n      \"names\": [\n{}\n      ],\n      \"tables\": [\n{}\n      ]\n    }}",
+            json_string(&section.name),
+            json_string(&section.description),
+            json_fields(&section.fields, "        "),
+            json_names(&section.names),
+            json_tables(&section.tables),
+        ))
+        .collect();
+    format!(
+        "{{\n  \"file\": [\n{}\n  ],\n  \"profile\": [\n{}\n  ],\n  \"sections\": [\n{}\n  ]\n}}\n",
+        json_fields(&get_file_schema(), "    "),
+        json_fields(&get_profile_schema(), "    "),
+        sections.join(",\n"),
+    )
+}
+
+/// 項目の説明をコメントとして出力する関数
+fn push_description(text: &mut String, prefix: &str, field: &FieldSchema) {
+    match &field.unit {
+        Some(unit) => text.push_str(&format!("{prefix}# {} [{}]\n", field.description, unit)),
+        None => text.push_str(&format!("{prefix}# {}\n", field.description)),
+    }
+}
+
+/// 名前の一覧をコメントとして出力する関数 (値のある一覧は1行に1つ、そのまま有効にできる形で出力する)
+fn push_names(text: &mut String, names: &NameSchema) {
+    if names.items.iter().all(|(_, value)| value.is_none()) {
+        let items: Vec<&str> = names.items.iter().map(|(name, _)| name.as_str()).collect();
+        text.push_str(&format!("# {}: {}\n", names.description, items.join(", ")));
+        return;
+    }
+    text.push_str(&format!("# {}\n", names.description));
+    let prefix = names.field.as_ref().map(|field| format!("{field}.")).unwrap_or_default();
+    for (name, value) in &names.items {
+        if let Some(value) = value {
+            text.push_str(&format!("# {prefix}{name} = {value}\n"));
+        }
+    }
+}
+
+/// コメント付きのデフォルト設定ファイルを出力する関数
+pub fn to_default_toml() -> String {
+    let mut text = String::from("# uline.toml (デフォルト値)\n# uline-config で生成しました。不要な項目は削除してもデフォルト値が使われます。\n");
+    let file_fields = get_file_schema();
+    let example = get_example_profile();
+
+    // default_profile はテーブルより前に記述する
+    if let Some(field) = file_fields.iter().find(|field| field.name == "default_profile") {
+        text.push('\n');
+        push_description(&mut text, "", field);
+        text.push_str(&format!("# default_profile = {}\n", example.get("name").map(|name| name.to_string()).unwrap_or_default()));
+    }
+
+    for section in get_schema() {
+        text.push_str(&format!("\n# {}\n[{}]\n", section.description, section.name));
+        for field in &section.fields {
+            push_description(&mut text, "", field);
+            // 名前やテーブルを記述する項目は、空のテーブルの代わりに名前の一覧や記述例を出力する
+            let names = section.names.iter().find(|names| names.field.as_deref() == Some(field.name.as_str()));
+            let has_table = section.tables.iter().any(|table| table.name == field.name);
+            match (&field.default, names) {
+                _ if field.flatten => {},
+                (Some(toml::Value::Table(table)), Some(names)) if table.is_empty() => push_names(&mut text, names),
+                (Some(toml::Value::Table(table)), _) if table.is_empty() && has_table => {},
+                (Some(value), _) => text.push_str(&format!("{} = {}\n", field.name, value)),
+                (None, _) => text.push_str(&format!("# {} = (未設定)\n", field.name)),
+            }
+        }
+        for names in section.names.iter().filter(|names| names.field.is_none()) {
+            push_names(&mut text, names);
+        }
+        for table in &section.tables {
+            text.push_str(&format!("# [{}.{}.1]\n", section.name, table.name));
+            for field in &table.fields {
+                push_description(&mut text, "# ", field);
+                if let Some(value) = &field.default {
+                    text.push_str(&format!("# {} = {}\n", field.name, value));
+                }
+            }
+        }
+    }
+
+    // 車両プロファイルの記述例
+    if let Some(field) = file_fields.iter().find(|field| field.name == "profile") {
+        text.push('\n');
+        push_description(&mut text, "", field);
+        text.push_str("# [[profile]]\n");
+        for field in get_profile_schema() {
+            push_description(&mut text, "# ", &field);
+            if let Some(value) = example.get(&field.name) {
+                text.push_str(&format!("# {} = {}\n", field.name, value));
+            }
+        }
+        let overrides = example
+            .as_table()
+            .into_iter()
+            .flatten()
+            .filter(|(key, _)| !VehicleProfile::FIELDS.iter().any(|field| field.name == key.as_str()));
+        for (name, table) in overrides {
+            text.push_str(&format!("# # 基本の設定から変更する項目 (テーブル毎に記述する)\n# [profile.{name}]\n"));
+            for (key, value) in table.as_table().into_iter().flatten() {
+                text.push_str(&format!("# {key} = {value}\n"));
+            }
+        }
+    }
+    text
+}
@@ -0,0 +1,265 @@
+//! 設定値の検証
+
+use std::collections::BTreeMap;
+
+use bveats_rs::{AtsKey, AtsVehicleSpec};
+
+use crate::output::{OutputLayout, PanelOutput, SoundOutput};
+
+use super::{KeyAction, Settings};
+
+/// 設定値の問題点
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct ValidationIssue {
+    /// 設定のテーブル名 (例: "vehicle")
+    pub section: String,
+    /// 問題点の説明
+    pub message: String,
+}
+
+/// 設定値の検証結果
+#[derive(Debug, Default)]
+#[derive(Clone)]
+pub struct ValidationReport {
+    /// 見つかった問題点
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    /// 問題点を追加する関数
+    pub fn push(&mut self, section: &str, message: String) {
+        self.issues.push(ValidationIssue { section: section.to_string(), message });
+    }
+    /// 問題がないか
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+    /// 人が読める形式のレポートを作成する関数
+    pub fn to_text(&self) -> String {
+        let mut text = String::from("# uline.toml 検証レポート\n");
+        if self.is_ok() {
+            text.push_str("問題はありません。\n");
+            return text;
+        }
+        text.push_str(&format!("{}件の問題があります。\n\n", self.issues.len()));
+        for issue in &self.issues {
+            text.push_str(&format!("[{}] {}\n", issue.section, issue.message));
+        }
+        text
+    }
+}
+
+/// AtsKeyの名前として正しいかを判断する関数
+fn is_key_name(name: &str) -> bool {
+    (0..=15).map(AtsKey::from).any(|key| format!("{key:?}") == name)
+}
+
+/// 0以上であることを確認する
+fn check_non_negative<T: PartialOrd + Default + std::fmt::Display>(report: &mut ValidationReport, section: &str, name: &str, value: T) {
+    if value < T::default() {
+        report.push(section, format!("{name} は0以上にしてください。({value})"));
+    }
+}
+
+/// 0より大きいことを確認する
+fn check_positive<T: PartialOrd + Default + std::fmt::Display>(report: &mut ValidationReport, section: &str, name: &str, value: T) {
+    if value <= T::default() {
+        report.push(section, format!("{name} は0より大きくしてください。({value})"));
+    }
+}
+
+/// ノッチの対応表を確認する (空の場合は線形変換なので確認しない)
+fn check_notch_map(report: &mut ValidationReport, name: &str, map: &[i32], input_notches: Option<i32>, output_notches: i32) {
+    if map.is_empty() {
+        return;
+    }
+    if let Some(input_notches) = input_notches {
+        if map.len() as i32 != input_notches + 1 {
+            report.push("vehicle", format!("{name} の要素数は入力ノッチの段数+1 ({}) にしてください。({})", input_notches + 1, map.len()));
+        }
+    }
+    if map.windows(2).any(|pair| pair[0] > pair[1]) {
+        report.push("vehicle", format!("{name} は小さいノッチから順に並べてください。({map:?})"));
+    }
+    if map.iter().any(|notch| !(0..=output_notches).contains(notch)) {
+        report.push("vehicle", format!("{name} の値は0〜{output_notches}にしてください。({map:?})"));
+    }
+}
+
+/// 出力先の対応表を確認する (`outputs` は出力名とインデックスの組)
+fn check_output_map(report: &mut ValidationReport, name: &str, map: &BTreeMap<String, i32>, outputs: &[(String, Option<usize>)]) {
+    for (output, index) in map {
+        if !outputs.iter().any(|(known, _)| known == output) {
+            report.push("output", format!("{name} の {output} は不明な出力です。"));
+        }
+        if !(-1..=255).contains(index) {
+            report.push("output", format!("{name} の {output} は-1〜255にしてください。({index})"));
+        }
+    }
+    let mut used: BTreeMap<usize, Vec<&str>> = BTreeMap::new();
+    for (output, index) in outputs {
+        if let Some(index) = index {
+            used.entry(*index).or_default().push(output);
+        }
+    }
+    for (index, outputs) in used.iter().filter(|(_, outputs)| outputs.len() > 1) {
+        report.push("output", format!("{name} のインデックス{index}に複数の出力が割り当てられています。({})", outputs.join(", ")));
+    }
+}
+
+impl Settings {
+    /// 設定値の範囲と整合性を検証する関数
+    /// (`spec` を指定した場合は車両諸元との整合性も確認する)
+    pub fn validate_report(&self, spec: Option<&AtsVehicleSpec>) -> ValidationReport {
+        let mut report = ValidationReport::default();
+
+        let vehicle = &self.vehicle;
+        check_positive(&mut report, "vehicle", "input_power_notches", vehicle.input_power_notches);
+        check_positive(&mut report, "vehicle", "input_brake_notches", vehicle.input_brake_notches);
+        check_positive(&mut report, "vehicle", "output_power_notches", vehicle.output_power_notches);
+        check_positive(&mut report, "vehicle", "output_brake_notches", vehicle.output_brake_notches);
+        check_non_negative(&mut report, "vehicle", "constant_start_speed", vehicle.constant_start_speed);
+        check_non_negative(&mut report, "vehicle", "yokusoku_start_speed", vehicle.yokusoku_start_speed);
+        for (name, gain) in [
+            ("constant_kp", vehicle.constant_kp), ("constant_ki", vehicle.constant_ki), ("constant_kd", vehicle.constant_kd),
+            ("holding_kp", vehicle.holding_kp), ("holding_ki", vehicle.holding_ki), ("holding_kd", vehicle.holding_kd),
+        ] {
+            check_non_negative(&mut report, "vehicle", name, gain);
+        }
+        if vehicle.holding_speed_offsets.iter().any(|offset| *offset < 0.0) {
+            report.push("vehicle", format!("holding_speed_offsets は0以上にしてください。({:?})", vehicle.holding_speed_offsets));
+        }
+        check_notch_map(&mut report, "power_notch_map", &vehicle.power_notch_map, Some(vehicle.input_power_notches), vehicle.output_power_notches);
+        check_notch_map(&mut report, "brake_notch_map", &vehicle.brake_notch_map, Some(vehicle.input_brake_notches), vehicle.output_brake_notches);
+        if !(0..=vehicle.output_brake_notches).contains(&vehicle.holding_max_brake_notch) {
+            report.push("vehicle", format!("holding_max_brake_notch は0〜{}にしてください。({})", vehicle.output_brake_notches, vehicle.holding_max_brake_notch));
+        }
+        check_notch_map(&mut report, "holding_notch_map", &vehicle.holding_notch_map, None, vehicle.get_holding_max_brake_notch());
+        if let Some(spec) = spec {
+            if vehicle.input_power_notches != spec.power_notches {
+                report.push("vehicle", format!("input_power_notches ({}) が車両の力行ノッチ数 ({}) と一致しません。", vehicle.input_power_notches, spec.power_notches));
+            }
+            if vehicle.input_brake_notches != spec.brake_notches {
+                report.push("vehicle", format!("input_brake_notches ({}) が車両のブレーキノッチ数 ({}) と一致しません。", vehicle.input_brake_notches, spec.brake_notches));
+            }
+        }
+
+        let atc = &self.atc;
+        check_non_negative(&mut report, "atc", "check_speed_margin", atc.check_speed_margin);
+        check_non_negative(&mut report, "atc", "half_brake_time", atc.half_brake_time);
+        check_non_negative(&mut report, "atc", "kakunin_limit_speed", atc.kakunin_limit_speed);
+        check_non_negative(&mut report, "atc", "hijo_limit_speed", atc.hijo_limit_speed);
+
+        let caution = &self.caution;
+        check_non_negative(&mut report, "caution", "approach_speed", caution.approach_speed);
+        check_non_negative(&mut report, "caution", "response_timeout", caution.response_timeout);
+
+        let pattern = &self.pattern;
+        check_positive(&mut report, "pattern", "deceleration", pattern.deceleration);
+        check_non_negative(&mut report, "pattern", "margin_distance", pattern.margin_distance);
+
+        let atc_release = &self.atc_release;
+        check_non_negative(&mut report, "atc_release", "confirm_time", atc_release.confirm_time);
+        check_non_negative(&mut report, "atc_release", "limit_speed", atc_release.limit_speed);
+        check_positive(&mut report, "atc_release", "blink_interval", atc_release.blink_interval);
+        for (name, key) in [("key", &atc_release.key), ("confirm_key", &atc_release.confirm_key)] {
+            if !is_key_name(key) {
+                report.push("atc_release", format!("{name} が不明なATSキーです。({key})"));
+            }
+        }
+
+        let irekae = &self.irekae;
+        check_non_negative(&mut report, "irekae", "check_speed_margin", irekae.check_speed_margin);
+        check_non_negative(&mut report, "irekae", "signal15_speed", irekae.signal15_speed);
+        check_non_negative(&mut report, "irekae", "signal25_speed", irekae.signal25_speed);
+
+        let ato = &self.ato;
+        check_non_negative(&mut report, "ato", "target_speed", ato.target_speed);
+        check_non_negative(&mut report, "ato", "p2_check_speed", ato.p2_check_speed);
+        check_non_negative(&mut report, "ato", "p3_check_speed", ato.p3_check_speed);
+        check_non_negative(&mut report, "ato", "p4_brake_time", ato.p4_brake_time);
+        check_non_negative(&mut report, "ato", "p5_lower_limit_speed", ato.p5_lower_limit_speed);
+        check_non_negative(&mut report, "ato", "power_notch_rate", ato.power_notch_rate);
+        check_non_negative(&mut report, "ato", "brake_notch_rate", ato.brake_notch_rate);
+        check_non_negative(&mut report, "ato", "power_off_delay", ato.power_off_delay);
+        check_non_negative(&mut report, "ato", "brake_hold_time", ato.brake_hold_time);
+
+        let tasc = &self.tasc;
+        check_non_negative(&mut report, "tasc", "beacon_distance_unit", tasc.beacon_distance_unit);
+        check_positive(&mut report, "tasc", "p1_distance", tasc.p1_distance);
+        check_positive(&mut report, "tasc", "p2_distance", tasc.p2_distance);
+        check_positive(&mut report, "tasc", "p90_distance", tasc.p90_distance);
+        for (station, distances) in &tasc.stations {
+            if station.parse::<i32>().is_err() {
+                report.push("tasc", format!("stations のキーは駅番号(整数)にしてください。({station})"));
+            }
+            for (name, distance) in [("p1_distance", distances.p1_distance), ("p2_distance", distances.p2_distance), ("p90_distance", distances.p90_distance)] {
+                if let Some(distance) = distance {
+                    check_positive(&mut report, "tasc", &format!("stations.{station}.{name}"), distance);
+                }
+            }
+        }
+        check_positive(&mut report, "tasc", "max_deceleration", tasc.max_deceleration);
+        if !(tasc.learning_rate > 0.0 && tasc.learning_rate <= 1.0) {
+            report.push("tasc", format!("learning_rate は0より大きく1以下にしてください。({})", tasc.learning_rate));
+        }
+        check_non_negative(&mut report, "tasc", "learning_settle_time", tasc.learning_settle_time);
+        check_non_negative(&mut report, "tasc", "learning_min_speed", tasc.learning_min_speed);
+
+        check_non_negative(&mut report, "tims", "display_draw_time", self.tims.display_draw_time);
+
+        for index in self.signal.index.keys() {
+            if !index.parse::<i32>().is_ok_and(|index| (0..=255).contains(&index)) {
+                report.push("signal", format!("index のキーは0〜255の信号インデックス(整数)にしてください。({index})"));
+            }
+        }
+
+        check_non_negative(&mut report, "gradient", "feed_forward_gain", self.gradient.feed_forward_gain);
+        check_positive(&mut report, "gradient", "max_acceleration", self.gradient.max_acceleration);
+
+        if !self.reload.key.is_empty() && !is_key_name(&self.reload.key) {
+            report.push("reload", format!("key が不明なATSキーです。({})", self.reload.key));
+        }
+
+        check_non_negative(&mut report, "door", "release_delay", self.door.release_delay);
+        if !(0..=self.vehicle.input_brake_notches).contains(&self.door.brake_notch) {
+            report.push("door", format!("brake_notch は0〜{}にしてください。({})", self.vehicle.input_brake_notches, self.door.brake_notch));
+        }
+
+        let layout = OutputLayout::new(&self.output);
+        let panel_outputs: Vec<_> = PanelOutput::ALL.iter().map(|output| (format!("{output:?}"), layout.get_panel_index(*output))).collect();
+        let sound_outputs: Vec<_> = SoundOutput::ALL.iter().map(|output| (format!("{output:?}"), layout.get_sound_index(*output))).collect();
+        check_output_map(&mut report, "panel", &self.output.panel, &panel_outputs);
+        check_output_map(&mut report, "sound", &self.output.sound, &sound_outputs);
+
+        for key in self.keys.bindings.keys() {
+            if !is_key_name(key) {
+                report.push("keys", format!("{key} が不明なATSキーです。"));
+            }
+        }
+        // 1つのキーに複数の動作を割り当てない ([keys] の割当てと各機能のキー設定)
+        let mut bindings: BTreeMap<&str, Vec<(String, KeyAction)>> = BTreeMap::new();
+        for (key, action) in self.keys.bindings.iter().filter(|(_, action)| **action != KeyAction::None) {
+            bindings.entry(key).or_default().push((format!("keys.{key} = {action:?}"), *action));
+        }
+        for (name, key, action) in [
+            ("atc_release.key", &self.atc_release.key, KeyAction::AtcRelease),
+            ("atc_release.confirm_key", &self.atc_release.confirm_key, KeyAction::AtcReleaseConfirm),
+            ("reload.key", &self.reload.key, KeyAction::ReloadSettings),
+        ] {
+            if !key.is_empty() {
+                bindings.entry(key).or_default().push((name.to_string(), action));
+            }
+        }
+        for (key, sources) in &bindings {
+            if sources.iter().any(|(_, action)| *action != sources[0].1) {
+                let names: Vec<&str> = sources.iter().map(|(name, _)| name.as_str()).collect();
+                report.push("keys", format!("{key} に複数の動作が割り当てられています。({})", names.join(", ")));
+            }
+        }
+
+        report
+    }
+}
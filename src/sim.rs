@@ -0,0 +1,7 @@
+//! BVEを使わずにATSプラグインを動作させるためのヘッドレス環境
+
+pub mod vehicle;
+pub mod host;
+pub mod scenario;
+pub mod trace;
+pub mod replay;
@@ -0,0 +1,85 @@
+use bveats_rs::{AtsHandles, AtsInit, AtsSound, AtsVehicleSpec, AtsVehicleState, BveAts};
+
+use crate::atc::uline_atc::ULineATC;
+use crate::settings::Settings;
+
+use super::{scenario::Scenario, vehicle::PointMassVehicle};
+
+/// panelのサイズ
+pub const PANEL_SIZE: usize = 256;
+/// soundのサイズ
+pub const SOUND_SIZE: usize = 256;
+
+/// BveAtsトレイトを実装したプラグインをBVEの代わりに駆動するホスト
+pub struct HeadlessHost<T: BveAts> {
+    /// 駆動するプラグイン
+    pub ats: T,
+    /// 車両モデル
+    pub vehicle: PointMassVehicle,
+    /// 車両諸元
+    pub spec: AtsVehicleSpec,
+    /// Elapseの呼び出し間隔 [ms]
+    pub interval: i32,
+    /// パネル配列
+    pub panel: Box<[i32; PANEL_SIZE]>,
+    /// サウンド配列
+    pub sound: Box<[i32; SOUND_SIZE]>,
+    /// 前回Elapseに渡した状態量
+    pub state: AtsVehicleState,
+    /// 前回Elapseで返されたハンドル出力
+    pub handles: AtsHandles,
+}
+
+impl<T: BveAts> HeadlessHost<T> {
+    pub fn new(ats: T, spec: AtsVehicleSpec, vehicle: PointMassVehicle, interval: i32) -> Self {
+        Self {
+            ats,
+            vehicle,
+            spec,
+            interval,
+            panel: Box::new([0; PANEL_SIZE]),
+            sound: Box::new([AtsSound::Stop as i32; SOUND_SIZE]),
+            state: AtsVehicleState::default(),
+            handles: AtsHandles::default(),
+        }
+    }
+
+    /// Load → SetVehicleSpec → Initialize の順にプラグインを起動する関数
+    pub fn start(&mut self, handle: AtsInit) {
+        self.ats.load();
+        self.ats.set_vehicle_spec(self.spec);
+        self.ats.initialize(handle);
+    }
+
+    /// Elapseを1回呼び出し、返されたハンドルで車両を進める関数
+    pub fn step(&mut self) -> AtsHandles {
+        self.state = self.vehicle.state();
+        self.handles = self.ats.elapse(self.state, self.panel.as_mut_slice(), self.sound.as_mut_slice());
+        self.vehicle.step(self.handles, self.interval);
+        self.handles
+    }
+
+    /// 現在時刻が`time`[ms]に達するまでElapseを繰り返す関数
+    pub fn run_until(&mut self, time: i32) {
+        while self.vehicle.time < time {
+            self.step();
+        }
+    }
+
+    /// プラグインを解放する関数
+    pub fn dispose(&mut self) {
+        self.ats.dispose();
+    }
+}
+
+impl HeadlessHost<ULineATC> {
+    /// 設定を指定してシナリオを最後まで実行し、実行後のホストを返す関数
+    /// (起動時に読み込んだ設定ファイルの代わりに `settings` を使い、設定ファイルの有無に依存しないようにする)
+    pub fn run_with_settings(scenario: &Scenario, settings: Settings, on_step: impl FnMut(&HeadlessHost<ULineATC>)) -> Self {
+        let mut host = scenario.create_host(ULineATC::default());
+        host.start(scenario.get_init().unwrap_or(AtsInit::Removed));
+        host.ats.apply_settings(settings);
+        scenario.play(&mut host, on_step);
+        host
+    }
+}
@@ -0,0 +1,37 @@
+//! 記録したセッションの再生
+//!
+//! 記録ファイルのヘッダに書き込んだ記録時の設定ファイルを使用して、ULineATCに再生する。
+//! (カレントディレクトリの設定ファイルの違いで差分が出ないようにする)
+
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use bveats_rs::record::{read_header, replay_session, ReplayMismatch};
+
+use crate::atc::uline_atc::ULineATC;
+
+/// 設定ファイルの内容から記録ファイルのヘッダを作成する関数 (1行目は設定ファイルのパスのコメント)
+pub fn to_record_header(path: &Path, text: &str) -> String {
+    format!("# 記録時の設定ファイル: {}\n{text}", path.display())
+}
+
+/// 記録時の設定ファイルを使用してセッションを再生し、記録と異なった出力を返す関数
+/// (ヘッダに記録時の設定ファイルがない場合はエラーを返す)
+pub fn replay_with_recorded_settings(log: &str) -> Result<Vec<ReplayMismatch>, String> {
+    static REPLAY_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    let settings = read_header(log).ok_or("記録ファイルに記録時の設定ファイルがありません。".to_string())?;
+    // 記録時の設定ファイルを一時フォルダに書き出して読み込ませる
+    let dir = std::env::temp_dir().join(format!("uline_replay_{}_{}", std::process::id(), REPLAY_COUNT.fetch_add(1, Ordering::Relaxed)));
+    std::fs::create_dir_all(&dir).map_err(|err| format!("一時フォルダを作成できません。({:?}) {err}", dir))?;
+    let path = dir.join("uline.toml");
+    let result = std::fs::write(&path, settings)
+        .map_err(|err| format!("記録時の設定ファイルを書き出せません。({:?}) {err}", path))
+        .and_then(|_| {
+            let mut ats = ULineATC::default();
+            ats.settings_path = Some(path);
+            replay_session(&mut ats, log)
+        });
+    let _ = std::fs::remove_dir_all(&dir);
+    result
+}
@@ -0,0 +1,358 @@
+use bveats_rs::{AtsBeaconData, AtsHorn, AtsInit, AtsKey, AtsVehicleSpec, BveAts};
+use log::info;
+use serde::Deserialize;
+
+use super::{host::HeadlessHost, vehicle::PointMassVehicle};
+
+/// シナリオファイルを表す
+#[derive(Debug, Default)]
+#[derive(Deserialize)]
+pub struct Scenario {
+    #[serde(default)]
+    pub host: ScenarioHost,
+    #[serde(default)]
+    pub spec: ScenarioSpec,
+    #[serde(default)]
+    pub vehicle: ScenarioVehicle,
+    /// 時刻・位置を条件に発生させるイベント (記述順に判定する)
+    #[serde(default, rename = "event")]
+    pub events: Vec<ScenarioEvent>,
+}
+
+/// ホストの設定
+#[derive(Debug)]
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct ScenarioHost {
+    /// Elapseの呼び出し間隔 [ms]
+    pub interval: i32,
+    /// シミュレーションする時間 [ms]
+    pub duration: i32,
+    /// ゲーム開始時のブレーキ弁の状態 ("removed", "emg", "svc")
+    pub init: String,
+}
+impl Default for ScenarioHost {
+    fn default() -> Self {
+        Self {
+            interval: 10,
+            duration: 60000,
+            init: "removed".to_string(),
+        }
+    }
+}
+
+/// SetVehicleSpecで渡す車両諸元
+#[derive(Debug)]
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct ScenarioSpec {
+    pub brake_notches: i32,
+    pub power_notches: i32,
+    pub ats_notch: i32,
+    pub b67_notch: i32,
+    pub cars: i32,
+}
+impl Default for ScenarioSpec {
+    fn default() -> Self {
+        Self {
+            brake_notches: 7,
+            power_notches: 4,
+            ats_notch: 1,
+            b67_notch: 5,
+            cars: 6,
+        }
+    }
+}
+
+/// 車両モデルの性能
+#[derive(Debug)]
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct ScenarioVehicle {
+    /// 出力される力行の段数
+    pub power_notches: i32,
+    /// 出力されるブレーキの段数
+    pub brake_notches: i32,
+    /// 最大加速度 [km/h/s]
+    pub max_acceleration: f32,
+    /// 常用最大減速度 [km/h/s]
+    pub max_deceleration: f32,
+    /// 非常減速度 [km/h/s]
+    pub emergency_deceleration: f32,
+    /// 走行抵抗による減速度 [km/h/s]
+    pub resistance: f32,
+    /// 初期位置 [m]
+    pub location: f64,
+    /// 初期速度 [km/h]
+    pub speed: f32,
+    /// 線路の勾配
+    pub gradient: Vec<ScenarioGradient>,
+}
+impl Default for ScenarioVehicle {
+    fn default() -> Self {
+        let vehicle = PointMassVehicle::default();
+        Self {
+            power_notches: vehicle.power_notches,
+            brake_notches: vehicle.brake_notches,
+            max_acceleration: vehicle.max_acceleration,
+            max_deceleration: vehicle.max_deceleration,
+            emergency_deceleration: vehicle.emergency_deceleration,
+            resistance: vehicle.resistance,
+            location: vehicle.location,
+            speed: vehicle.speed,
+            gradient: Vec::new(),
+        }
+    }
+}
+
+/// 線路の勾配の変化点
+#[derive(Debug)]
+#[derive(Deserialize)]
+pub struct ScenarioGradient {
+    /// 変化点の位置 [m]
+    pub location: f64,
+    /// この位置から先の勾配 [‰] (上り勾配を正)
+    pub gradient: f32,
+}
+
+/// シナリオ中のイベント
+///
+/// `time`, `location`, `stopped` のうち指定されたものを全て満たした時に1回だけ発生する。
+#[derive(Debug)]
+#[derive(Deserialize)]
+pub struct ScenarioEvent {
+    /// 発生時刻 [ms] (この時刻以降)
+    pub time: Option<i32>,
+    /// 発生位置 [m] (この位置以降)
+    pub location: Option<f64>,
+    /// 停車中に限るか
+    #[serde(default)]
+    pub stopped: bool,
+    #[serde(flatten)]
+    pub action: ScenarioAction,
+}
+
+/// イベントで呼び出すプラグインの関数
+#[derive(Debug)]
+#[derive(Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ScenarioAction {
+    SetSignal { signal: i32 },
+    SetBeaconData {
+        beacon_type: i32,
+        #[serde(default)]
+        signal: i32,
+        #[serde(default)]
+        distance: f32,
+        #[serde(default)]
+        optional: i32,
+    },
+    SetPower { notch: i32 },
+    SetBrake { notch: i32 },
+    SetReverser { notch: i32 },
+    /// `key` はATSキーの名前 ("S", "A1", "C2" など)
+    KeyDown { key: String },
+    KeyUp { key: String },
+    /// `horn` は 0:警笛1, 1:警笛2, 2:ミュージックホーン
+    HornBlow { horn: i32 },
+    DoorOpen,
+    DoorClose,
+}
+
+/// ATSキーの名前からAtsKeyを取得する関数
+fn get_key(name: &str) -> Result<AtsKey, String> {
+    (0..=15)
+        .map(AtsKey::from)
+        .find(|key| format!("{key:?}") == name)
+        .ok_or(format!("不明なATSキーです。({name})"))
+}
+
+impl Scenario {
+    /// TOML文字列からシナリオを読み込む関数
+    pub fn parse(data: &str) -> Result<Self, String> {
+        let scenario: Scenario = toml::from_str(data).map_err(|err| format!("シナリオのパースに失敗しました。 {err}"))?;
+        scenario.get_init()?;
+        for event in &scenario.events {
+            match &event.action {
+                ScenarioAction::KeyDown { key } | ScenarioAction::KeyUp { key } => { get_key(key)?; }
+                ScenarioAction::HornBlow { horn } if !(0..=2).contains(horn) => {
+                    return Err(format!("不明な警笛のタイプです。({horn})"));
+                }
+                _ => {}
+            }
+        }
+        Ok(scenario)
+    }
+
+    /// シナリオファイルを読み込む関数
+    pub fn load(path: &std::path::Path) -> Result<Self, String> {
+        let data = std::fs::read_to_string(path).map_err(|err| format!("シナリオの読み込みに失敗しました。({path:?}) {err}"))?;
+        Self::parse(&data)
+    }
+
+    /// ゲーム開始時のブレーキ弁の状態
+    pub fn get_init(&self) -> Result<AtsInit, String> {
+        match self.host.init.as_str() {
+            "removed" => Ok(AtsInit::Removed),
+            "emg" => Ok(AtsInit::Emg),
+            "svc" => Ok(AtsInit::Svc),
+            init => Err(format!("不明なブレーキ弁の状態です。({init})")),
+        }
+    }
+
+    /// シナリオの設定からホストを作成する関数
+    pub fn create_host<T: BveAts>(&self, ats: T) -> HeadlessHost<T> {
+        let spec = AtsVehicleSpec {
+            brake_notches: self.spec.brake_notches,
+            power_notches: self.spec.power_notches,
+            ats_notch: self.spec.ats_notch,
+            b67_notch: self.spec.b67_notch,
+            cars: self.spec.cars,
+        };
+        let mut vehicle = PointMassVehicle::default();
+        vehicle.power_notches = self.vehicle.power_notches;
+        vehicle.brake_notches = self.vehicle.brake_notches;
+        vehicle.max_acceleration = self.vehicle.max_acceleration;
+        vehicle.max_deceleration = self.vehicle.max_deceleration;
+        vehicle.emergency_deceleration = self.vehicle.emergency_deceleration;
+        vehicle.resistance = self.vehicle.resistance;
+        vehicle.location = self.vehicle.location;
+        vehicle.speed = self.vehicle.speed;
+        vehicle.gradients = self.vehicle.gradient.iter().map(|gradient| (gradient.location, gradient.gradient)).collect();
+        vehicle.gradients.sort_by(|a, b| a.0.total_cmp(&b.0));
+        HeadlessHost::new(ats, spec, vehicle, self.host.interval)
+    }
+
+    /// プラグインを起動し、シナリオを最後まで実行する関数 (`on_step` はElapseごとに呼び出される)
+    pub fn run<T: BveAts>(&self, host: &mut HeadlessHost<T>, on_step: impl FnMut(&HeadlessHost<T>)) {
+        host.start(self.get_init().unwrap_or(AtsInit::Removed));
+        self.play(host, on_step);
+    }
+
+    /// 起動済みのプラグインでシナリオを最後まで実行する関数
+    pub fn play<T: BveAts>(&self, host: &mut HeadlessHost<T>, mut on_step: impl FnMut(&HeadlessHost<T>)) {
+        let mut fired = vec![false; self.events.len()];
+        while host.vehicle.time < self.host.duration {
+            for (event, fired) in self.events.iter().zip(fired.iter_mut()) {
+                if !*fired && event.is_ready(&host.vehicle) {
+                    *fired = true;
+                    info!("[Scenario] {}ms {:.2}m {:?}", host.vehicle.time, host.vehicle.location, event.action);
+                    event.action.apply(&mut host.ats);
+                }
+            }
+            host.step();
+            on_step(host);
+        }
+        host.dispose();
+    }
+}
+
+impl ScenarioEvent {
+    /// イベントの発生条件を満たしているか
+    fn is_ready(&self, vehicle: &PointMassVehicle) -> bool {
+        self.time.is_none_or(|time| time <= vehicle.time)
+            && self.location.is_none_or(|location| location <= vehicle.location)
+            && (!self.stopped || vehicle.speed == 0.0)
+    }
+}
+
+impl ScenarioAction {
+    /// プラグインの対応する関数を呼び出す関数
+    pub fn apply<T: BveAts>(&self, ats: &mut T) {
+        match self {
+            Self::SetSignal { signal } => ats.set_signal(*signal),
+            Self::SetBeaconData { beacon_type, signal, distance, optional } => ats.set_beacon_data(AtsBeaconData {
+                beacon_type: *beacon_type,
+                signal: *signal,
+                distance: *distance,
+                optional: *optional,
+            }),
+            Self::SetPower { notch } => ats.set_power(*notch),
+            Self::SetBrake { notch } => ats.set_brake(*notch),
+            Self::SetReverser { notch } => ats.set_reverser(*notch),
+            Self::KeyDown { key } => if let Ok(key) = get_key(key) { ats.key_down(key) },
+            Self::KeyUp { key } => if let Ok(key) = get_key(key) { ats.key_up(key) },
+            Self::HornBlow { horn } => ats.horn_blow(AtsHorn::from(*horn)),
+            Self::DoorOpen => ats.door_open(),
+            Self::DoorClose => ats.door_close(),
+        }
+    }
+}
+
+/// シナリオの記述を組み立てる (テストなどで共通の部分を何度も書かないようにする)
+///
+/// 時刻0で信号を受信し、逆転ハンドルを前進にした状態から始まり、`events` で加えたイベントを続ける。
+#[derive(Debug, Clone)]
+pub struct ScenarioBuilder {
+    /// Elapseの呼び出し間隔 [ms]
+    interval: i32,
+    /// シミュレーションする時間 [ms]
+    duration: i32,
+    /// 時刻0で受信する信号インデックス
+    signal: i32,
+    /// 車両モデルの項目と値
+    vehicle: Vec<(String, String)>,
+    /// 位置0から先の線路の勾配 [‰]
+    gradient: Option<f32>,
+    /// ATOで出発するか
+    ato_departure: bool,
+    /// 追加するイベント (`[[event]]` のTOML)
+    events: String,
+}
+
+impl ScenarioBuilder {
+    /// Elapseの呼び出し間隔と時間、時刻0で受信する信号インデックスを指定して作成する関数
+    pub fn new(interval: i32, duration: i32, signal: i32) -> Self {
+        Self { interval, duration, signal, vehicle: Vec::new(), gradient: None, ato_departure: false, events: String::new() }
+    }
+
+    /// 車両モデルの項目を設定する関数 (`speed`, `resistance` など)
+    pub fn vehicle(mut self, key: &str, value: impl std::fmt::Display) -> Self {
+        self.vehicle.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// 位置0から先の線路の勾配を設定する関数 [‰]
+    pub fn gradient(mut self, gradient: f32) -> Self {
+        self.gradient = Some(gradient);
+        self
+    }
+
+    /// 時刻0で逆転ハンドルを前進にする代わりに、ATOで出発する関数
+    /// (2秒後に運転切換スイッチをATOに、2.5秒後に逆転ハンドルを前進にし、3秒後に出発ボタンを押す)
+    pub fn ato_departure(mut self) -> Self {
+        self.ato_departure = true;
+        self
+    }
+
+    /// イベントを追加する関数 (`[[event]]` のTOML)
+    pub fn events(mut self, events: &str) -> Self {
+        self.events.push_str(events);
+        self.events.push('\n');
+        self
+    }
+
+    /// シナリオの記述を出力する関数
+    pub fn build(&self) -> String {
+        let mut text = format!("[host]\ninterval = {}\nduration = {}\n", self.interval, self.duration);
+        if !self.vehicle.is_empty() {
+            text.push_str("\n[vehicle]\n");
+            for (key, value) in &self.vehicle {
+                text.push_str(&format!("{key} = {value}\n"));
+            }
+        }
+        if let Some(gradient) = self.gradient {
+            text.push_str(&format!("\n[[vehicle.gradient]]\nlocation = 0.0\ngradient = {gradient}\n"));
+        }
+        text.push_str(&format!("\n[[event]]\ntime = 0\naction = \"set_signal\"\nsignal = {}\n", self.signal));
+        if self.ato_departure {
+            text.push_str("\n[[event]]\ntime = 2000\naction = \"key_down\"\nkey = \"C2\"\n");
+            text.push_str("\n[[event]]\ntime = 2500\naction = \"set_reverser\"\nnotch = 1\n");
+            text.push_str("\n[[event]]\ntime = 3000\naction = \"key_down\"\nkey = \"S\"\n");
+        } else {
+            text.push_str("\n[[event]]\ntime = 0\naction = \"set_reverser\"\nnotch = 1\n");
+        }
+        text.push_str(&self.events);
+        text
+    }
+}
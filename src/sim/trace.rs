@@ -0,0 +1,78 @@
+use std::fmt::Write;
+
+use bveats_rs::AtsHandles;
+
+use crate::atc::uline_atc::{AtcBrakeStatus, ULineATC};
+
+use super::host::HeadlessHost;
+
+/// 1回のElapseで記録した状態
+#[derive(Debug, Clone)]
+pub struct TraceFrame {
+    /// Elapseに渡した時刻 [ms]
+    pub time: i32,
+    /// Elapseに渡した列車位置 [m]
+    pub location: f64,
+    /// Elapseに渡した列車速度 [km/h]
+    pub speed: f32,
+    /// ATCブレーキの種別
+    pub atc_brake_status: AtcBrakeStatus,
+    /// 出力されたハンドル
+    pub handles: AtsHandles,
+    /// 記録対象のパネルの値
+    pub panel: Vec<i32>,
+    /// 記録対象のサウンドの値
+    pub sound: Vec<i32>,
+}
+
+/// Elapseごとの状態を記録する
+#[derive(Debug, Default)]
+pub struct TraceRecorder {
+    /// 記録するパネルのインデックス
+    pub panel_indices: Vec<usize>,
+    /// 記録するサウンドのインデックス
+    pub sound_indices: Vec<usize>,
+    /// 記録した状態
+    pub frames: Vec<TraceFrame>,
+}
+
+impl TraceRecorder {
+    pub fn new(panel_indices: Vec<usize>, sound_indices: Vec<usize>) -> Self {
+        Self {
+            panel_indices,
+            sound_indices,
+            frames: Vec::new(),
+        }
+    }
+
+    /// 直前のElapseの結果を記録する関数
+    pub fn record(&mut self, host: &HeadlessHost<ULineATC>) {
+        self.frames.push(TraceFrame {
+            time: host.state.time,
+            location: host.state.location,
+            speed: host.state.speed,
+            atc_brake_status: host.ats.atc_brake_status,
+            handles: host.handles,
+            panel: self.panel_indices.iter().map(|&i| host.panel[i]).collect(),
+            sound: self.sound_indices.iter().map(|&i| host.sound[i]).collect(),
+        });
+    }
+
+    /// 記録をテキスト形式で出力する関数 (ゴールデンファイルの形式)
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        let _ = writeln!(text, "# time location speed atc_brake_status power brake reverser constant_speed | panel{:?} | sound{:?}",
+            self.panel_indices, self.sound_indices);
+        for frame in &self.frames {
+            let _ = writeln!(text, "{} {:.3} {:.3} {:?} {} {} {} {} | {} | {}",
+                frame.time, frame.location, frame.speed, frame.atc_brake_status,
+                frame.handles.power, frame.handles.brake, frame.handles.reverser, frame.handles.constant_speed,
+                join(&frame.panel), join(&frame.sound));
+        }
+        text
+    }
+}
+
+fn join(values: &[i32]) -> String {
+    values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(" ")
+}
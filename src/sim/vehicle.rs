@@ -0,0 +1,136 @@
+use bveats_rs::{AtsConstantSpeed, AtsHandles, AtsVehicleState};
+
+use crate::atc::gradient::get_gradient_acceleration;
+
+/// 質点として扱う簡易的な車両モデル
+#[derive(Debug, Clone)]
+pub struct PointMassVehicle {
+    /// 出力される力行の段数
+    pub power_notches: i32,
+    /// 出力されるブレーキの段数 (これを超えると非常ブレーキ)
+    pub brake_notches: i32,
+    /// 最大加速度 [km/h/s]
+    pub max_acceleration: f32,
+    /// 常用最大減速度 [km/h/s]
+    pub max_deceleration: f32,
+    /// 非常減速度 [km/h/s]
+    pub emergency_deceleration: f32,
+    /// 走行抵抗による減速度 [km/h/s]
+    pub resistance: f32,
+    /// 線路の勾配 (位置[m], その位置から先の勾配[‰]) (位置の順, 上り勾配を正)
+    pub gradients: Vec<(f64, f32)>,
+
+    /// 列車位置 [m]
+    pub location: f64,
+    /// 列車速度 [km/h]
+    pub speed: f32,
+    /// 現在時刻 [ms]
+    pub time: i32,
+    /// 定速制御が有効か
+    pub is_constant_speed: bool,
+
+    /// 前回のハンドル出力
+    handles: AtsHandles,
+}
+impl Default for PointMassVehicle {
+    fn default() -> Self {
+        Self {
+            power_notches: 31,
+            brake_notches: 31,
+            max_acceleration: 3.0,
+            max_deceleration: 3.5,
+            emergency_deceleration: 4.5,
+            resistance: 0.05,
+            gradients: Vec::new(),
+            location: 0.0,
+            speed: 0.0,
+            time: 0,
+            is_constant_speed: false,
+            handles: AtsHandles::default(),
+        }
+    }
+}
+
+impl PointMassVehicle {
+    /// 現在の状態量をBVEに渡す形式で取得する関数
+    pub fn state(&self) -> AtsVehicleState {
+        let brake_ratio = if self.is_emergency() {
+            1.1
+        } else {
+            (self.handles.brake as f32 / self.brake_notches as f32).clamp(0.0, 1.0)
+        };
+        let power_ratio = (self.handles.power as f32 / self.power_notches as f32).clamp(0.0, 1.0);
+        AtsVehicleState {
+            location: self.location,
+            speed: self.speed,
+            time: self.time,
+            bc_pressure: 400.0 * brake_ratio,
+            mr_pressure: 800.0,
+            er_pressure: 490.0 - 150.0 * brake_ratio,
+            bp_pressure: 490.0 - 150.0 * brake_ratio,
+            sap_pressure: 400.0 * brake_ratio,
+            current: if self.handles.brake == 0 { 400.0 * power_ratio } else { 0.0 },
+        }
+    }
+
+    /// 現在位置の勾配 [‰]
+    pub fn get_gradient(&self) -> f32 {
+        self.gradients.iter()
+            .take_while(|(location, _)| *location <= self.location)
+            .last()
+            .map_or(0.0, |(_, gradient)| *gradient)
+    }
+
+    /// 非常ブレーキが出力されているか
+    fn is_emergency(&self) -> bool {
+        self.handles.brake > self.brake_notches
+    }
+
+    /// ハンドル出力に従って車両を`delta`[ms]だけ進める関数
+    pub fn step(&mut self, handles: AtsHandles, delta: i32) {
+        self.handles = handles;
+        match AtsConstantSpeed::from(handles.constant_speed) {
+            AtsConstantSpeed::Enable => self.is_constant_speed = true,
+            AtsConstantSpeed::Disable => self.is_constant_speed = false,
+            _ => {}
+        }
+
+        let delta_s = delta as f32 / 1000.0;
+        let direction = self.speed.signum();
+
+        // 力行による加速度 (ブレーキ中は力行しない)
+        let traction = if handles.brake == 0 && handles.reverser != 0 {
+            let ratio = (handles.power as f32 / self.power_notches as f32).clamp(0.0, 1.0);
+            self.max_acceleration * ratio * handles.reverser.signum() as f32
+        } else {
+            0.0
+        };
+        // ブレーキと走行抵抗による減速度 (進行方向と逆向きにかかる)
+        let braking = if self.is_emergency() {
+            self.emergency_deceleration
+        } else {
+            self.max_deceleration * (handles.brake as f32 / self.brake_notches as f32).clamp(0.0, 1.0)
+        } + self.resistance;
+
+        let mut acceleration = traction;
+        if self.is_constant_speed && self.speed != 0.0 && traction.signum() == direction {
+            // 定速制御中は現在の速度を超えて加速しない
+            acceleration = acceleration.min(braking).max(-braking);
+        }
+        acceleration += get_gradient_acceleration(self.get_gradient());
+
+        let mut speed = self.speed + acceleration * delta_s;
+        if speed != 0.0 {
+            let before = speed;
+            speed -= braking * delta_s * speed.signum();
+            // ブレーキで逆走はしない
+            if before.signum() != speed.signum() {
+                speed = 0.0;
+            }
+        }
+
+        self.location += ((self.speed + speed) / 2.0 * delta_s / 3.6) as f64;
+        self.speed = speed;
+        self.time += delta;
+    }
+}
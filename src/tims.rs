@@ -1,5 +1,8 @@
 use bveats_rs::{AtsBeaconData, AtsHorn, AtsInit, AtsKey, AtsSound, AtsVehicleSpec, AtsVehicleState};
 
+use crate::ato::tasc_report::TascStop;
+use crate::output::{OutputLayout, PanelOutput, SoundOutput};
+
 #[repr(i32)]
 #[derive(Debug, Clone, Copy, PartialEq)]
 #[allow(unused)]
@@ -23,6 +26,7 @@ enum ULineStation {
     S16SeishinMinami = 2,
     S17SeishinChuo = 1,
 }
+#[allow(clippy::derivable_impls)]
 impl Default for ULineStation {
 	fn default() -> Self {
 		Self::None
@@ -50,6 +54,7 @@ impl ULineTrainType {
 		unsafe { std::mem::transmute(self) }
 	}
 }
+#[allow(clippy::derivable_impls)]
 impl Default for ULineTrainType {
 	fn default() -> Self {
 		Self::None
@@ -109,12 +114,15 @@ impl TimsPosition {
 }
 
 /// TIMSを表す
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Default)]
 pub struct TIMS {
 	/// TIMS 始発駅
     start_station: ULineStation,
     /// TIMS 終着駅
     destination: ULineStation,
+    /// TIMS 次の停車駅
+    next_station: ULineStation,
     /// TIMS 列車種別
     train_type: ULineTrainType,
     /// TIMS 列車番号
@@ -129,6 +137,12 @@ pub struct TIMS {
 
     /// 前回、回送放送が流れた時刻
     pub out_of_service_sound_time: i32,
+
+    /// パネル・サウンドの出力先
+    pub output: OutputLayout,
+
+    /// 表示中のTASCの停車結果 (発車すると消去する)
+    pub tasc_stop: Option<TascStop>,
 }
 
 impl TIMS {
@@ -153,33 +167,55 @@ impl TIMS {
 		let minutes = total_second / 60 % 60;
 		let seconds = total_second % 60;
 
-        _panel[49] = (_state.speed * 10.0) as i32;
-        _panel[50] = _state.bc_pressure as i32;
-        _panel[51] = _state.mr_pressure as i32;
-        _panel[52] = _state.current.abs() as i32;
-		_panel[101] = self.operation_number / 10;
-		_panel[102] = self.operation_number % 10;
-		_panel[103] = self.train_type.to_i32();
-		_panel[104] = self.destination.to_i32();
+        self.output.set_panel(_panel, PanelOutput::Speed, (_state.speed * 10.0) as i32);
+        self.output.set_panel(_panel, PanelOutput::BcPressure, _state.bc_pressure as i32);
+        self.output.set_panel(_panel, PanelOutput::MrPressure, _state.mr_pressure as i32);
+        self.output.set_panel(_panel, PanelOutput::Current, _state.current.abs() as i32);
+		self.output.set_panel(_panel, PanelOutput::OperationNumber10, self.operation_number / 10);
+		self.output.set_panel(_panel, PanelOutput::OperationNumber1, self.operation_number % 10);
+		self.output.set_panel(_panel, PanelOutput::TrainType, self.train_type.to_i32());
+		self.output.set_panel(_panel, PanelOutput::Destination, self.destination.to_i32());
 		// _panel[105] = 
 		// _panel[106] =
-		_panel[107] = hours / 10;
-		_panel[108] = hours % 10;
-		_panel[109] = minutes / 10;
-		_panel[110] = minutes % 10;
-		_panel[111] = seconds / 10;
-		_panel[112] = seconds % 10;
+		self.output.set_panel(_panel, PanelOutput::Hour10, hours / 10);
+		self.output.set_panel(_panel, PanelOutput::Hour1, hours % 10);
+		self.output.set_panel(_panel, PanelOutput::Minute10, minutes / 10);
+		self.output.set_panel(_panel, PanelOutput::Minute1, minutes % 10);
+		self.output.set_panel(_panel, PanelOutput::Second10, seconds / 10);
+		self.output.set_panel(_panel, PanelOutput::Second1, seconds % 10);
 
-        _panel[113] = (_state.speed / 10.0 % 10.0) as i32;
-        _panel[114] = (_state.speed % 10.0) as i32;
+        self.output.set_panel(_panel, PanelOutput::SpeedDigit10, (_state.speed / 10.0 % 10.0) as i32);
+        self.output.set_panel(_panel, PanelOutput::SpeedDigit1, (_state.speed % 10.0) as i32);
 
-        _panel[116] = self.position.get_tims_distance_prefix();
+        self.output.set_panel(_panel, PanelOutput::DistancePrefix, self.position.get_tims_distance_prefix());
         let distance = self.position.get_tims_distance(_state.location as f32, self.is_position_negative).abs() as i32;
-        _panel[117] = distance / 10000 % 10;
-        _panel[118] = distance / 1000 % 10;
-        _panel[119] = distance / 100 % 10;
-        _panel[120] = distance / 10 % 10;
+        self.output.set_panel(_panel, PanelOutput::Distance10, distance / 10000 % 10);
+        self.output.set_panel(_panel, PanelOutput::Distance1, distance / 1000 % 10);
+        self.output.set_panel(_panel, PanelOutput::Distance01, distance / 100 % 10);
+        self.output.set_panel(_panel, PanelOutput::Distance001, distance / 10 % 10);
+
+        // TASCの停止精度 (停車中のみ表示する)
+        if _state.speed != 0.0 {
+            self.tasc_stop = None;
+        }
+        let (accuracy, error) = match &self.tasc_stop {
+            Some(stop) => (stop.get_accuracy().to_panel(), (stop.get_error().abs() * 100.0).round() as i32),
+            None => (0, 0),
+        };
+        self.output.set_panel(_panel, PanelOutput::TascAccuracy, accuracy);
+        self.output.set_panel(_panel, PanelOutput::TascError, error);
 	}
+    /// TIMSの始発駅と行先を取得する関数
+    pub(super) fn get_stations(&self) -> (i32, i32) {
+        (self.start_station.to_i32(), self.destination.to_i32())
+    }
+    /// TIMSの次の停車駅を取得する関数 (設定されていない場合はNone)
+    pub(super) fn get_next_station(&self) -> Option<i32> {
+        match self.next_station {
+            ULineStation::None => None,
+            station => Some(station.to_i32()),
+        }
+    }
 	pub(super) fn set_power(&mut self, _notch: i32) {
     }
 
@@ -206,6 +242,7 @@ impl TIMS {
 
     pub(super) fn set_signal(&mut self, _signal: i32) {
     }
+	#[allow(clippy::collapsible_match, clippy::missing_transmute_annotations)]
 	pub(super) fn set_beacon_data(&mut self, data: AtsBeaconData) {
 		match data.beacon_type {
             11 => { // 始発駅設定
@@ -254,6 +291,11 @@ impl TIMS {
                     _ => self.is_position_negative = true,
                 }
             },
+            18 => { // 次の停車駅設定
+                if 0 <= data.optional && data.optional <= 17 {
+                    self.next_station = unsafe { std::mem::transmute(data.optional) };
+                }
+            },
             _ => {}
         }
 	}
@@ -268,10 +310,10 @@ impl TIMS {
         }
 
         if self.out_of_service_sound_time + 10000 < _state.time {
-            sound[100] = AtsSound::Play as i32;
+            self.output.set_sound(sound, SoundOutput::OutOfService, AtsSound::Play as i32);
             self.out_of_service_sound_time = _state.time;
         } else {
-            sound[100] = AtsSound::Continue as i32;
+            self.output.set_sound(sound, SoundOutput::OutOfService, AtsSound::Continue as i32);
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,441 @@
+//! ATCブレーキのゴールデントレースによる回帰テスト
+//!
+//! `UPDATE_GOLDEN=1 cargo test --test atc_brake` でゴールデンファイルを更新する。
+
+use std::path::PathBuf;
+
+use bveats_rs::AtsSound;
+
+use uline_atc::atc::uline_atc::AtcBrakeStatus;
+use uline_atc::settings::{HisetsuBrake, Settings};
+use uline_atc::sim::host::HeadlessHost;
+use uline_atc::sim::scenario::{Scenario, ScenarioBuilder};
+use uline_atc::sim::trace::TraceRecorder;
+
+/// 記録するパネル (力行/ブレーキノッチ, 定速/抑速, ATC信号, 異常, 非常/確認運転, ATC種別, ATC開放, 非常ブレーキSW, 保安ブレーキ)
+const PANEL_INDICES: [usize; 22] = [9, 10, 19, 20, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47, 48];
+/// 記録するサウンド (ATC現示変化ベル, ATC速度制限警報ブザー)
+const SOUND_INDICES: [usize; 2] = [2, 3];
+/// 入換のトレースで追加して記録するパネル (入換信号)
+const IREKAE_PANEL_INDICES: [usize; 2] = [53, 54];
+/// パターン式ATCのトレースで追加して記録するパネル (パターン速度)
+const PATTERN_PANEL_INDICES: [usize; 2] = [55, 56];
+/// 注意喚起のトレースで追加して記録するサウンド (ATC注意喚起警報チャイム)
+const CAUTION_SOUND_INDICES: [usize; 1] = [4];
+/// 切換要請のトレースで追加して記録するサウンド (ATC切換要請警報ブザー)
+const CHANGEOVER_SOUND_INDICES: [usize; 1] = [5];
+
+/// シナリオを実行してトレースを取得する
+fn run_trace(scenario: &str, settings: Settings) -> TraceRecorder {
+    run_trace_with(scenario, settings, &[], &[])
+}
+
+/// 追加のパネル・サウンドも記録して、シナリオを実行してトレースを取得する
+fn run_trace_with(scenario: &str, settings: Settings, panel: &[usize], sound: &[usize]) -> TraceRecorder {
+    let scenario = Scenario::parse(scenario).expect("シナリオが不正です");
+    let mut recorder = TraceRecorder::new([&PANEL_INDICES, panel].concat(), [&SOUND_INDICES, sound].concat());
+    HeadlessHost::run_with_settings(&scenario, settings, |host| recorder.record(host));
+    recorder
+}
+
+/// ゴールデンファイルと比較する
+fn assert_golden(name: &str, recorder: &TraceRecorder) {
+    let actual = recorder.to_text();
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden/atc_brake").join(format!("{name}.trace"));
+    if std::env::var("UPDATE_GOLDEN").is_ok() {
+        std::fs::write(&path, &actual).expect("ゴールデンファイルの書き込みに失敗しました");
+        return;
+    }
+    let expected = std::fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("ゴールデンファイルがありません ({path:?})。UPDATE_GOLDEN=1 で作成してください"));
+    for (line, (expected, actual)) in expected.lines().zip(actual.lines()).enumerate() {
+        assert_eq!(expected, actual, "{name}.trace の {} 行目が異なります", line + 1);
+    }
+    assert_eq!(expected.lines().count(), actual.lines().count(), "{name}.trace の行数が異なります");
+}
+
+/// 指定した信号を受信した状態で、初速から力行し続けるシナリオ
+fn overspeed_scenario(signal: i32, speed: f32, duration: i32) -> String {
+    ScenarioBuilder::new(50, duration, signal).vehicle("speed", speed).events(r#"
+        [[event]]
+        time = 0
+        action = "set_power"
+        notch = 4
+    "#).build()
+}
+
+#[test]
+fn signal02_emergency_brake() {
+    let recorder = run_trace(&overspeed_scenario(0, 10.0, 3000), Settings::default());
+    assert!(recorder.frames.iter().all(|frame| frame.atc_brake_status == AtcBrakeStatus::EmergencyBraking));
+    assert_golden("signal02", &recorder);
+}
+
+#[test]
+fn signal02_hijo_unten() {
+    let scenario = overspeed_scenario(0, 0.0, 12000) + r#"
+        [[event]]
+        time = 0
+        action = "key_down"
+        key = "D"
+    "#;
+    let recorder = run_trace(&scenario, Settings::default());
+    assert_golden("signal02_hijo", &recorder);
+}
+
+#[test]
+fn signal01_overspeed() {
+    let recorder = run_trace(&overspeed_scenario(1, 0.0, 5000), Settings::default());
+    assert_golden("signal01", &recorder);
+}
+
+#[test]
+fn signal01_kakunin_unten() {
+    let scenario = overspeed_scenario(1, 0.0, 12000) + r#"
+        [[event]]
+        time = 0
+        action = "key_down"
+        key = "E"
+    "#;
+    let recorder = run_trace(&scenario, Settings::default());
+    assert_golden("signal01_kakunin", &recorder);
+}
+
+#[test]
+fn signal15_overspeed() {
+    let recorder = run_trace(&overspeed_scenario(2, 10.0, 8000), Settings::default());
+    assert_golden("signal15", &recorder);
+}
+
+#[test]
+fn signal25_overspeed() {
+    let recorder = run_trace(&overspeed_scenario(3, 20.0, 8000), Settings::default());
+    assert_golden("signal25", &recorder);
+}
+
+#[test]
+fn signal45_overspeed() {
+    let recorder = run_trace(&overspeed_scenario(4, 40.0, 8000), Settings::default());
+    assert_golden("signal45", &recorder);
+}
+
+#[test]
+fn signal60_overspeed() {
+    let recorder = run_trace(&overspeed_scenario(5, 55.0, 8000), Settings::default());
+    assert_golden("signal60", &recorder);
+}
+
+#[test]
+fn signal75_overspeed() {
+    let recorder = run_trace(&overspeed_scenario(6, 70.0, 8000), Settings::default());
+    assert_golden("signal75", &recorder);
+}
+
+#[test]
+fn signal90_overspeed() {
+    let recorder = run_trace(&overspeed_scenario(7, 85.0, 8000), Settings::default());
+    assert_golden("signal90", &recorder);
+}
+
+#[test]
+fn signal_downgrade() {
+    let scenario = r#"
+        [host]
+        interval = 50
+        duration = 12000
+
+        [vehicle]
+        speed = 80.0
+
+        [[event]]
+        time = 0
+        action = "set_signal"
+        signal = 7
+
+        [[event]]
+        time = 0
+        action = "set_reverser"
+        notch = 1
+
+        [[event]]
+        time = 1000
+        action = "set_signal"
+        signal = 4
+    "#;
+    let recorder = run_trace(scenario, Settings::default());
+    assert_golden("signal_downgrade", &recorder);
+}
+
+#[test]
+fn check_speed_margin() {
+    for margin in [0.0, 1.5, 3.0] {
+        let mut settings = Settings::default();
+        settings.atc.check_speed_margin = margin;
+        let recorder = run_trace(&overspeed_scenario(4, 40.0, 5000), settings);
+
+        // 照査速度(45km/h + マージン)を超えた最初のElapseでブレーキが掛かる
+        let braking = recorder.frames.iter()
+            .position(|frame| frame.atc_brake_status != AtcBrakeStatus::Passing)
+            .expect("ATCブレーキが掛かっていません");
+        assert!(braking > 0);
+        assert!(recorder.frames[braking - 1].speed < 45.0 + margin);
+        assert!(recorder.frames[braking].speed >= 45.0 + margin);
+        assert!(matches!(recorder.frames[braking].atc_brake_status, AtcBrakeStatus::HalfBraking(_)));
+    }
+
+    let mut settings = Settings::default();
+    settings.atc.check_speed_margin = 3.0;
+    let recorder = run_trace(&overspeed_scenario(4, 40.0, 5000), settings);
+    assert_golden("signal45_margin3", &recorder);
+}
+
+/// 運転切換スイッチを入換にして、指定した信号を受信した状態で力行し続けるシナリオ
+fn irekae_scenario(signal: i32, speed: f32, duration: i32) -> String {
+    overspeed_scenario(signal, speed, duration) + r#"
+        [[event]]
+        time = 0
+        action = "key_down"
+        key = "C1"
+    "#
+}
+
+#[test]
+fn irekae15_overspeed() {
+    let recorder = run_trace_with(&irekae_scenario(8, 10.0, 6000), Settings::default(), &IREKAE_PANEL_INDICES, &CHANGEOVER_SOUND_INDICES);
+    // 入換では緩和ブレーキを使わない
+    assert!(recorder.frames.iter().all(|frame| !matches!(frame.atc_brake_status, AtcBrakeStatus::HalfBraking(_))));
+    assert!(recorder.frames.iter().any(|frame| frame.atc_brake_status == AtcBrakeStatus::FullBraking));
+    assert_golden("irekae15", &recorder);
+}
+
+#[test]
+fn irekae25_overspeed() {
+    let recorder = run_trace_with(&irekae_scenario(9, 20.0, 6000), Settings::default(), &IREKAE_PANEL_INDICES, &CHANGEOVER_SOUND_INDICES);
+    assert_golden("irekae25", &recorder);
+}
+
+#[test]
+fn irekae_main_line_signal() {
+    let scenario = irekae_scenario(9, 10.0, 6000) + r#"
+        [[event]]
+        time = 2000
+        action = "set_signal"
+        signal = 5
+    "#;
+    let recorder = run_trace_with(&scenario, Settings::default(), &IREKAE_PANEL_INDICES, &CHANGEOVER_SOUND_INDICES);
+    // 本線の信号を受信すると切換要請ブザーが鳴動し、停止するまでブレーキが作動する
+    let last = recorder.frames.last().unwrap();
+    assert_eq!(last.sound[2], 0);
+    assert_eq!(last.speed, 0.0);
+    assert_golden("irekae_main_line", &recorder);
+}
+
+/// 運転切換スイッチを非設にして、02信号の区間を力行し続けるシナリオ
+fn hisetsu_scenario(speed: f32, duration: i32) -> String {
+    overspeed_scenario(0, speed, duration) + r#"
+        [[event]]
+        time = 0
+        action = "key_down"
+        key = "C1"
+
+        [[event]]
+        time = 0
+        action = "key_down"
+        key = "C1"
+
+        [[event]]
+        time = 2000
+        action = "set_signal"
+        signal = 5
+
+        [[event]]
+        time = 3000
+        action = "set_signal"
+        signal = 0
+    "#
+}
+
+#[test]
+fn hisetsu_signal_received() {
+    let recorder = run_trace_with(&hisetsu_scenario(20.0, 6000), Settings::default(), &IREKAE_PANEL_INDICES, &CHANGEOVER_SOUND_INDICES);
+    // 02信号の間はブレーキが作動しない
+    assert!(recorder.frames.iter().filter(|frame| frame.time < 2000).all(|frame| frame.handles.brake == 0 && frame.sound[2] == AtsSound::Stop as i32));
+    // 信号を受信すると、02信号に戻っても切り換えるまで切換要請ブザーとブレーキが継続する
+    assert!(recorder.frames.iter().filter(|frame| frame.time > 2000).all(|frame| frame.atc_brake_status == AtcBrakeStatus::FullBraking));
+    assert_eq!(recorder.frames.last().unwrap().sound[2], AtsSound::PlayLooping as i32);
+    assert_golden("hisetsu_signal", &recorder);
+}
+
+#[test]
+fn hisetsu_changeover_release() {
+    let scenario = hisetsu_scenario(20.0, 6000) + r#"
+        [[event]]
+        time = 4000
+        action = "key_down"
+        key = "C2"
+
+        [[event]]
+        time = 4000
+        action = "key_down"
+        key = "C2"
+    "#;
+    let recorder = run_trace_with(&scenario, Settings::default(), &[], &CHANGEOVER_SOUND_INDICES);
+    // 運転切換スイッチを切り換えると切換要請が解除される
+    let last = recorder.frames.last().unwrap();
+    assert_ne!(last.sound[2], AtsSound::PlayLooping as i32);
+}
+
+#[test]
+fn hisetsu_brake_none() {
+    let mut settings = Settings::default();
+    settings.hisetsu.brake = HisetsuBrake::None;
+    let recorder = run_trace_with(&hisetsu_scenario(20.0, 6000), settings, &[], &CHANGEOVER_SOUND_INDICES);
+    // ブザーのみでブレーキは作動しない
+    assert!(recorder.frames.iter().all(|frame| frame.handles.brake == 0));
+    assert_eq!(recorder.frames.last().unwrap().sound[2], AtsSound::PlayLooping as i32);
+}
+
+/// 02信号で停止した状態からATC開放して力行し続けるシナリオ
+fn atc_release_scenario(confirm_time: i32) -> String {
+    overspeed_scenario(0, 0.0, 20000) + &format!(r#"
+        [[event]]
+        time = 1000
+        action = "key_down"
+        key = "F"
+
+        [[event]]
+        time = {confirm_time}
+        action = "key_down"
+        key = "G"
+    "#)
+}
+
+#[test]
+fn atc_release_limit_speed() {
+    let recorder = run_trace_with(&atc_release_scenario(2000), Settings::default(), &IREKAE_PANEL_INDICES, &CHANGEOVER_SOUND_INDICES);
+    // ATC開放中は02信号でも開放時の制限速度まで力行できる
+    assert!(recorder.frames.iter().any(|frame| frame.speed > 20.0));
+    assert!(recorder.frames.iter().all(|frame| frame.speed < 27.5));
+    assert!(recorder.frames.iter().any(|frame| frame.atc_brake_status == AtcBrakeStatus::FullBraking));
+    assert!(recorder.frames.iter().all(|frame| frame.atc_brake_status != AtcBrakeStatus::EmergencyBraking || frame.time <= 2000));
+    assert_golden("atc_release", &recorder);
+}
+
+#[test]
+fn atc_release_seal_timeout() {
+    let recorder = run_trace(&atc_release_scenario(5000), Settings::default());
+    // 確認キーの入力が遅れた場合は開放されない
+    assert!(recorder.frames.iter().all(|frame| frame.atc_brake_status == AtcBrakeStatus::EmergencyBraking));
+}
+
+#[test]
+fn atc_release_history() {
+    let scenario = Scenario::parse(&(atc_release_scenario(2000) + r#"
+        [[event]]
+        time = 10000
+        action = "key_down"
+        key = "F"
+    "#)).unwrap();
+    let host = HeadlessHost::run_with_settings(&scenario, Settings::default(), |_| {});
+    // 開放と復帰がそれぞれ記録される
+    let history = &host.ats.atc_release_history;
+    assert_eq!(history.len(), 2);
+    assert!(history[0].enable);
+    // 操作時刻は直前のElapseの時刻となる
+    assert!((1950..=2000).contains(&history[0].time));
+    assert!(!history[1].enable);
+    assert!((9950..=10000).contains(&history[1].time));
+}
+
+/// 90信号で力行し続け、300m先の15km/hの目標を受信するシナリオ
+fn pattern_scenario() -> String {
+    overspeed_scenario(7, 60.0, 40000) + r#"
+        [[event]]
+        time = 0
+        action = "set_beacon_data"
+        beacon_type = 20
+        optional = 150300
+    "#
+}
+
+#[test]
+fn pattern_brake() {
+    let mut settings = Settings::default();
+    settings.pattern.enable = true;
+    let recorder = run_trace_with(&pattern_scenario(), settings, &[IREKAE_PANEL_INDICES, PATTERN_PANEL_INDICES].concat(), &CHANGEOVER_SOUND_INDICES);
+    // 目標地点以降は目標速度(+マージン)以下で走行する (力行し続けるので緩和ブレーキ中にわずかに超える)
+    assert!(recorder.frames.iter().filter(|frame| frame.location >= 300.0).all(|frame| frame.speed < 15.0 + 1.5 + 0.5));
+    // 90信号のまま、パターンに当たってブレーキが作動する
+    assert!(recorder.frames.iter().any(|frame| frame.atc_brake_status != AtcBrakeStatus::Passing));
+    assert_golden("pattern", &recorder);
+}
+
+#[test]
+fn pattern_disabled() {
+    let recorder = run_trace(&pattern_scenario(), Settings::default());
+    // パターン式ATCが無効の場合は地上子を無視する
+    assert!(recorder.frames.iter().filter(|frame| frame.speed < 90.0).all(|frame| frame.atc_brake_status == AtcBrakeStatus::Passing));
+}
+
+/// 45信号で照査速度の手前を惰行し続けるシナリオ
+fn caution_coasting_scenario(brake: i32) -> String {
+    overspeed_scenario(4, 44.0, 12000) + &format!(r#"
+        [[event]]
+        time = 0
+        action = "set_power"
+        notch = 0
+
+        [[event]]
+        time = 0
+        action = "set_brake"
+        notch = {brake}
+    "#)
+}
+
+/// 注意喚起チャイムを鳴動させた回数
+fn count_caution_chime(recorder: &TraceRecorder) -> usize {
+    recorder.frames.iter().filter(|frame| frame.sound[2] == AtsSound::Play as i32).count()
+}
+
+#[test]
+fn caution_approach() {
+    let mut settings = Settings::default();
+    settings.caution.approach = true;
+    let recorder = run_trace_with(&overspeed_scenario(4, 40.0, 6000), settings, &[IREKAE_PANEL_INDICES, PATTERN_PANEL_INDICES].concat(), &[CAUTION_SOUND_INDICES, CHANGEOVER_SOUND_INDICES].concat());
+    // ATCブレーキが作動する前に注意喚起チャイムが鳴動する
+    let chime = recorder.frames.iter().position(|frame| frame.sound[2] == AtsSound::Play as i32).unwrap();
+    let brake = recorder.frames.iter().position(|frame| frame.atc_brake_status != AtcBrakeStatus::Passing).unwrap();
+    assert!(chime < brake);
+    assert_golden("caution_approach", &recorder);
+}
+
+#[test]
+fn caution_downgrade() {
+    let mut settings = Settings::default();
+    settings.caution.downgrade = true;
+    let scenario = overspeed_scenario(5, 50.0, 3000) + r#"
+        [[event]]
+        time = 1000
+        action = "set_signal"
+        signal = 4
+    "#;
+    let recorder = run_trace_with(&scenario, settings, &[], &CAUTION_SOUND_INDICES);
+    // 現示ダウン時に新しい信号速度を超えていると鳴動する
+    let chime = recorder.frames.iter().find(|frame| frame.sound[2] == AtsSound::Play as i32).unwrap();
+    assert_eq!(chime.time, 1000);
+}
+
+#[test]
+fn caution_response_timeout() {
+    let mut settings = Settings::default();
+    settings.caution.approach = true;
+    // ブレーキを操作しない場合は一定時間ごとに再鳴動する
+    let recorder = run_trace_with(&caution_coasting_scenario(0), settings.clone(), &[], &CAUTION_SOUND_INDICES);
+    assert_eq!(count_caution_chime(&recorder), 3);
+    // ブレーキを操作した場合は再鳴動しない
+    let recorder = run_trace_with(&caution_coasting_scenario(1), settings, &[], &CAUTION_SOUND_INDICES);
+    assert_eq!(count_caution_chime(&recorder), 1);
+    // 無効 (既定) の場合は鳴動しない
+    let recorder = run_trace_with(&caution_coasting_scenario(0), Settings::default(), &[], &CAUTION_SOUND_INDICES);
+    assert_eq!(count_caution_chime(&recorder), 0);
+}
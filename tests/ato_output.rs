@@ -0,0 +1,141 @@
+//! ATOの出力ノッチの整形のテスト
+
+use bveats_rs::{AtsConstantSpeed, AtsHandles};
+use uline_atc::ato::output_shaper::OutputShaper;
+use uline_atc::settings::{ATOSettings, Settings};
+use uline_atc::sim::{host::HeadlessHost, scenario::{Scenario, ScenarioBuilder}};
+
+fn handles(power: i32, brake: i32) -> AtsHandles {
+    AtsHandles { power, brake, reverser: 1, constant_speed: AtsConstantSpeed::Disable as i32 }
+}
+
+/// 出力ノッチの整形を有効にした設定
+fn shaped_settings() -> ATOSettings {
+    ATOSettings { power_notch_rate: 40.0, brake_notch_rate: 40.0, power_off_delay: 500, brake_hold_time: 1000, ..Default::default() }
+}
+
+/// 10ms毎に目標のノッチを与え、各フレームの(時刻, 出力ノッチ)を返す
+fn shape(shaper: &mut OutputShaper, settings: &ATOSettings, start: i32, end: i32, target: AtsHandles) -> Vec<(i32, AtsHandles)> {
+    (start..end).step_by(10).map(|time| (time, shaper.shape(settings, 31, time, target))).collect()
+}
+
+#[test]
+fn notch_rate_is_limited() {
+    let settings = shaped_settings();
+    let mut shaper = OutputShaper::default();
+    // 0→B31は1秒間に40ノッチの変化率で投入する
+    let frames = shape(&mut shaper, &settings, 0, 1000, handles(0, 31));
+    assert!(frames.windows(2).all(|frames| (frames[1].1.brake - frames[0].1.brake).abs() <= 1));
+    let (time, _) = frames.iter().find(|(_, handles)| handles.brake == 31).unwrap();
+    assert!((770..=790).contains(time), "{time}ms");
+    // 制限しない場合は直ちに投入する
+    let settings = ATOSettings { brake_notch_rate: 0.0, ..shaped_settings() };
+    let mut shaper = OutputShaper::default();
+    shaper.shape(&settings, 31, 0, handles(0, 0));
+    assert_eq!(shaper.shape(&settings, 31, 10, handles(0, 31)).brake, 31);
+}
+
+#[test]
+fn power_off_delay_before_braking() {
+    let settings = shaped_settings();
+    let mut shaper = OutputShaper::default();
+    shape(&mut shaper, &settings, 0, 1000, handles(31, 0));
+    assert_eq!(shaper.get_notches(), (31, 0));
+    // 力行を0まで戻し、戻し切ってから力行OFF遅延時間が経過するまではブレーキを投入しない
+    let frames = shape(&mut shaper, &settings, 1000, 3000, handles(0, 10));
+    let (power_off, _) = frames.iter().find(|(_, handles)| handles.power == 0).unwrap();
+    let (brake_on, _) = frames.iter().find(|(_, handles)| handles.brake > 0).unwrap();
+    assert!(brake_on - power_off >= settings.power_off_delay, "{power_off}ms {brake_on}ms");
+    assert!(frames.iter().all(|(_, handles)| handles.power == 0 || handles.brake == 0));
+    assert_eq!(shaper.get_notches(), (0, 10));
+}
+
+#[test]
+fn brake_hold_before_power() {
+    let settings = shaped_settings();
+    let mut shaper = OutputShaper::default();
+    shape(&mut shaper, &settings, 0, 300, handles(0, 8));
+    // ブレーキを投入してから保持時間が経過するまでは緩解しない
+    let frames = shape(&mut shaper, &settings, 300, 3000, handles(20, 0));
+    for (time, handles) in &frames {
+        if *time < settings.brake_hold_time {
+            assert_eq!((handles.power, handles.brake), (0, 8), "{time}ms");
+        }
+    }
+    // 緩解してから力行する
+    let (release, _) = frames.iter().find(|(_, handles)| handles.brake == 0).unwrap();
+    let (power_on, _) = frames.iter().find(|(_, handles)| handles.power > 0).unwrap();
+    assert!(release < power_on && *release >= settings.brake_hold_time, "{release}ms {power_on}ms");
+    assert_eq!(shaper.get_notches(), (20, 0));
+}
+
+#[test]
+fn default_is_pass_through() {
+    // 既定では整形せずに目標のノッチをそのまま出力する
+    let settings = ATOSettings::default();
+    let mut shaper = OutputShaper::default();
+    let result = shaper.shape(&settings, 31, 0, handles(31, 0));
+    assert_eq!((result.power, result.brake), (31, 0));
+    let result = shaper.shape(&settings, 31, 10, handles(0, 10));
+    assert_eq!((result.power, result.brake), (0, 10));
+    let result = shaper.shape(&settings, 31, 20, handles(20, 0));
+    assert_eq!((result.power, result.brake), (20, 0));
+    // 後から整形を有効にした場合は、出力しているノッチから変化させる
+    assert_eq!(shaper.shape(&shaped_settings(), 31, 30, handles(20, 0)).power, 20);
+}
+
+#[test]
+fn emergency_brake_is_not_limited() {
+    let settings = shaped_settings();
+    let mut shaper = OutputShaper::default();
+    shape(&mut shaper, &settings, 0, 1000, handles(31, 0));
+    let result = shaper.shape(&settings, 31, 1000, handles(0, 32));
+    assert_eq!((result.power, result.brake), (0, 32));
+    // 非常ブレーキの後は常用最大ブレーキから戻す
+    assert_eq!(shaper.shape(&settings, 31, 1010, handles(0, 0)).brake, 31);
+}
+
+/// 出発から停止目標までATOで走行し、各フレームの出力ハンドルを返す
+fn run_ato(settings: Settings) -> (f64, Vec<AtsHandles>) {
+    let scenario = ScenarioBuilder::new(10, 150000, 5).ato_departure().events(r#"
+        [[event]]
+        location = 649.5
+        action = "set_beacon_data"
+        beacon_type = 1
+        optional = 0
+
+        [[event]]
+        location = 974.5
+        action = "set_beacon_data"
+        beacon_type = 2
+        optional = 0
+    "#).build();
+    let scenario = Scenario::parse(&scenario).unwrap();
+    let mut frames = Vec::new();
+    let host = HeadlessHost::run_with_settings(&scenario, settings, |host| if host.state.time >= 3000 { frames.push(host.handles) });
+    (host.state.location, frames)
+}
+
+/// 1フレームで変化したノッチの最大値 (力行, ブレーキ)
+fn get_max_step(frames: &[AtsHandles]) -> (i32, i32) {
+    frames.windows(2).fold((0, 0), |(power, brake), frames| (
+        power.max((frames[1].power - frames[0].power).abs()),
+        brake.max((frames[1].brake - frames[0].brake).abs()),
+    ))
+}
+
+#[test]
+fn ato_output_is_smooth() {
+    let (location, frames) = run_ato(Settings { ato: shaped_settings(), ..Default::default() });
+    assert!((location - 1000.0).abs() < 1.0, "{location}");
+    // 出力ノッチは1フレームで大きく変化せず、力行とブレーキを同時に出力しない
+    // (ブレーキは出力の際に車両の段数を超えると1段増やすため2段まで)
+    let (power, brake) = get_max_step(&frames);
+    assert!(power <= 1 && brake <= 2, "P{power} B{brake}");
+    assert!(frames.iter().all(|handles| handles.power == 0 || handles.brake == 0));
+
+    // 整形しない場合 (既定) はノッチが急変する
+    let (_, frames) = run_ato(Settings::default());
+    let (power, brake) = get_max_step(&frames);
+    assert!(power > 1 || brake > 2, "P{power} B{brake}");
+}
@@ -0,0 +1,105 @@
+//! 戸閉保安のテスト
+
+use bveats_rs::AtsHandles;
+use uline_atc::settings::Settings;
+use uline_atc::sim::host::HeadlessHost;
+use uline_atc::sim::scenario::{Scenario, ScenarioBuilder};
+
+/// 停車中にドアを開け、力行したまま2秒後にドアを閉めるシナリオ
+fn door_scenario(events: &str) -> String {
+    ScenarioBuilder::new(50, 6000, 4).events(r#"
+        [[event]]
+        time = 0
+        action = "door_open"
+
+        [[event]]
+        time = 500
+        action = "set_power"
+        notch = 4
+
+        [[event]]
+        time = 2000
+        action = "door_close"
+    "#).events(events).build()
+}
+
+/// シナリオを実行し、各フレームの(時刻, 出力ハンドル, 戸閉保安の表示灯)を返す
+fn run(scenario: &str, settings: Settings) -> Vec<(i32, AtsHandles, i32)> {
+    let scenario = Scenario::parse(scenario).expect("シナリオが不正です");
+    let mut frames = Vec::new();
+    HeadlessHost::run_with_settings(&scenario, settings, |host| frames.push((host.state.time, host.handles, host.panel[57])));
+    frames
+}
+
+/// 戸閉保安を有効にした設定
+fn door_settings() -> Settings {
+    let mut settings = Settings::default();
+    settings.door.enable = true;
+    settings
+}
+
+#[test]
+fn door_interlock_cuts_power() {
+    let mut settings = door_settings();
+    settings.tims.display_draw_time = 0;
+    settings.door.release_delay = 1000;
+    let frames = run(&door_scenario(""), settings);
+    // ドアが開いている間と、閉まってから解除遅延時間が経過するまでは力行できない
+    for (time, handles, lamp) in &frames {
+        if (600..2900).contains(time) {
+            assert_eq!(handles.power, 0, "{time}ms");
+            assert_eq!(*lamp, 1, "{time}ms");
+        }
+        if *time >= 3200 {
+            assert!(handles.power > 0, "{time}ms");
+            assert_eq!(*lamp, 0, "{time}ms");
+        }
+    }
+}
+
+#[test]
+fn door_interlock_holds_brake() {
+    let mut settings = door_settings();
+    settings.door.brake_notch = 4;
+    let expected = settings.vehicle.get_output_brake_notch(4);
+    let frames = run(&door_scenario(""), settings);
+    let (_, handles, _) = frames.iter().find(|(time, _, _)| *time == 1000).unwrap();
+    assert!(handles.brake >= expected);
+    let (_, handles, _) = frames.last().unwrap();
+    assert_eq!(handles.brake, 0);
+}
+
+#[test]
+fn door_interlock_disabled() {
+    // 既定では戸閉保安を使用しない
+    let frames = run(&door_scenario(""), Settings::default());
+    let (_, handles, _) = frames.iter().find(|(time, _, _)| *time == 1000).unwrap();
+    assert!(handles.power > 0);
+}
+
+#[test]
+fn door_interlock_blocks_ato_departure() {
+    // ATOに切り換え、力行を戻してから出発スイッチを操作する
+    let ato_events = |departure: i32| format!(r#"
+        [[event]]
+        time = 100
+        action = "key_down"
+        key = "C2"
+
+        [[event]]
+        time = 1000
+        action = "set_power"
+        notch = 0
+
+        [[event]]
+        time = {departure}
+        action = "key_down"
+        key = "S"
+    "#);
+    // ドアが開いている間の出発操作は受け付けない
+    let frames = run(&door_scenario(&ato_events(1500)), door_settings());
+    assert!(frames.iter().all(|(_, handles, _)| handles.power == 0));
+    // ドアが閉まった後は出発できる
+    let frames = run(&door_scenario(&ato_events(2500)), door_settings());
+    assert!(frames.iter().any(|(_, handles, _)| handles.power > 0));
+}
@@ -0,0 +1,143 @@
+//! 勾配のテスト
+
+use uline_atc::atc::gradient::GradientTracker;
+use uline_atc::atc::uline_atc::ULineATC;
+use uline_atc::ato::tasc_report::StopAccuracy;
+use uline_atc::settings::Settings;
+use uline_atc::sim::{host::HeadlessHost, scenario::{Scenario, ScenarioBuilder}};
+
+/// ATOで走行するシナリオ (勾配地上子で線路と同じ勾配を受信する)
+fn ato_scenario(gradient: f32, events: &str) -> String {
+    ScenarioBuilder::new(10, 150000, 5).gradient(gradient).ato_departure().events(&format!(r#"
+        [[event]]
+        time = 0
+        action = "set_beacon_data"
+        beacon_type = 30
+        optional = {}
+    "#, (gradient * 10.0) as i32)).events(events).build()
+}
+
+/// シナリオを実行し、各フレームの(時刻, 位置, 速度)を返す
+fn run(scenario: &str, settings: Settings) -> (HeadlessHost<ULineATC>, Vec<(i32, f64, f32)>) {
+    let scenario = Scenario::parse(scenario).expect("シナリオが不正です");
+    let mut frames = Vec::new();
+    let host = HeadlessHost::run_with_settings(&scenario, settings, |host| frames.push((host.state.time, host.state.location, host.state.speed)));
+    (host, frames)
+}
+
+/// TASCのパターン地上子
+const TASC_BEACONS: &str = r#"
+    [[event]]
+    location = 649.5
+    action = "set_beacon_data"
+    beacon_type = 1
+
+    [[event]]
+    location = 974.5
+    action = "set_beacon_data"
+    beacon_type = 2
+"#;
+
+/// 勾配を打ち消すノッチを加えるかを指定した設定
+fn gradient_settings(enable: bool) -> Settings {
+    let mut settings = Settings::default();
+    settings.gradient.enable = enable;
+    settings
+}
+
+#[test]
+fn gradient_beacons() {
+    let mut gradient = GradientTracker::default();
+    gradient.set_from_beacon(-350);
+    assert_eq!(gradient.get_gradient(), -35.0);
+    // 100m先から10‰の上り勾配, 300m先から25‰の下り勾配
+    gradient.add_change_from_beacon(1000100, 0.0);
+    gradient.add_change_from_beacon(-2500300, 0.0);
+    assert_eq!(gradient.get_changes(), &[(100.0, 10.0), (300.0, -25.0)]);
+    assert_eq!(gradient.get_average_gradient(0.0, 200.0), (-35.0 * 100.0 + 10.0 * 100.0) / 200.0);
+    gradient.elapse(99.0);
+    assert_eq!(gradient.get_gradient(), -35.0);
+    gradient.elapse(100.0);
+    assert_eq!(gradient.get_gradient(), 10.0);
+    // 手前の位置の予告で、それより先の予告を置き換える
+    gradient.add_change_from_beacon(200, 100.0);
+    assert_eq!(gradient.get_changes(), &[(300.0, 0.0)]);
+    gradient.elapse(300.0);
+    assert_eq!(gradient.get_gradient(), 0.0);
+    assert!(gradient.get_changes().is_empty());
+}
+
+#[test]
+fn ato_holds_target_speed_on_gradient() {
+    for gradient in [-35.0, 35.0] {
+        // 目標速度(57km/h)付近で走行している間の最高速度
+        let max_speed = |enable: bool| {
+            let (_, frames) = run(&ato_scenario(gradient, TASC_BEACONS), gradient_settings(enable));
+            frames.iter()
+                .filter(|(time, location, _)| *time > 40000 && *location < 600.0)
+                .fold(0.0f32, |max, (_, _, speed)| max.max(*speed))
+        };
+        let speed = max_speed(true);
+        assert!((55.0..=57.5).contains(&speed), "{gradient}‰: {speed}");
+        // 勾配を考慮しない場合は上り勾配で目標速度に届かず、下り勾配で超過する
+        let speed = max_speed(false);
+        assert!(!(55.0..=57.5).contains(&speed), "{gradient}‰: {speed}");
+    }
+}
+
+#[test]
+fn tasc_stops_on_gradient() {
+    let accuracy = |enable: bool| {
+        let (host, _) = run(&ato_scenario(35.0, TASC_BEACONS), gradient_settings(enable));
+        host.ats.tasc_report.entries.last().unwrap().stop.get_accuracy()
+    };
+    assert_eq!(accuracy(true), StopAccuracy::Accurate);
+    assert_eq!(accuracy(false), StopAccuracy::Short);
+}
+
+/// 定速制御・抑速制御の目標速度からの最大の偏差を返す
+fn max_speed_control_error(gradient: f32, notch: i32, enable: bool) -> f32 {
+    let scenario = ScenarioBuilder::new(10, 60000, 7).vehicle("speed", 60.0).gradient(gradient).events(&format!(r#"
+        [[event]]
+        time = 0
+        action = "set_beacon_data"
+        beacon_type = 30
+        optional = {}
+
+        [[event]]
+        time = 0
+        action = "set_power"
+        notch = 4
+
+        [[event]]
+        time = 100
+        action = "set_power"
+        notch = {notch}
+    "#, (gradient * 10.0) as i32)).build();
+    let scenario = Scenario::parse(&scenario).expect("シナリオが不正です");
+    let mut max_error = 0.0f32;
+    HeadlessHost::run_with_settings(&scenario, gradient_settings(enable), |host| {
+        let target_speed = if host.ats.is_constant_control { host.ats.constant_target_speed } else { host.ats.holding_base_speed };
+        assert!(host.ats.is_constant_control || host.ats.is_holding_control || host.state.time <= 100);
+        if host.state.time > 100 {
+            max_error = max_error.max((host.state.speed - target_speed).abs());
+        }
+    });
+    max_error
+}
+
+#[test]
+fn speed_control_on_gradient() {
+    // 定速制御 (P4→P3)
+    for gradient in [-35.0, 35.0] {
+        let error = max_speed_control_error(gradient, 3, true);
+        assert!(error < 0.5, "{gradient}‰: {error}");
+        let error = max_speed_control_error(gradient, 3, false);
+        assert!(error > 1.0, "{gradient}‰: {error}");
+    }
+    // 抑速制御 (抑速1)
+    let error = max_speed_control_error(-35.0, -1, true);
+    assert!(error < 0.5, "{error}");
+    let error = max_speed_control_error(-35.0, -1, false);
+    assert!(error > 1.0, "{error}");
+}
@@ -0,0 +1,78 @@
+//! パネル・サウンドの出力先の対応表のテスト
+
+use bveats_rs::AtsSound;
+
+use uline_atc::output::{OutputLayout, PanelOutput, SoundOutput};
+use uline_atc::settings::Settings;
+use uline_atc::sim::host::HeadlessHost;
+use uline_atc::sim::scenario::Scenario;
+use uline_atc::sim::trace::TraceRecorder;
+
+#[test]
+fn default_layout() {
+    let layout = OutputLayout::default();
+    assert_eq!(layout.get_panel_index(PanelOutput::PowerNotch), Some(9));
+    assert_eq!(layout.get_panel_index(PanelOutput::Signal02), Some(31));
+    assert_eq!(layout.get_panel_index(PanelOutput::Distance001), Some(120));
+    assert_eq!(layout.get_sound_index(SoundOutput::AtcBell), Some(2));
+    assert_eq!(layout.get_sound_index(SoundOutput::EmgBrake), Some(106));
+    // 全ての出力が既定のインデックスを持ち、重複しない
+    for (i, output) in PanelOutput::ALL.iter().enumerate() {
+        assert_eq!(*output as usize, i);
+    }
+    for (i, output) in SoundOutput::ALL.iter().enumerate() {
+        assert_eq!(*output as usize, i);
+    }
+    assert!(Settings::default().validate_report(None).is_ok());
+}
+
+#[test]
+fn custom_layout() {
+    let settings: Settings = toml::from_str(r#"
+        [output.panel]
+        Signal02 = 131
+        Signal01 = -1
+
+        [output.sound]
+        AtcBell = 12
+    "#).unwrap();
+    let layout = OutputLayout::new(&settings.output);
+    assert_eq!(layout.get_panel_index(PanelOutput::Signal02), Some(131));
+    assert_eq!(layout.get_panel_index(PanelOutput::Signal01), None);
+    // 記述のない出力は既定のインデックス
+    assert_eq!(layout.get_panel_index(PanelOutput::Signal15), Some(33));
+    assert_eq!(layout.get_sound_index(SoundOutput::AtcBell), Some(12));
+
+    let scenario = Scenario::parse(r#"
+        [host]
+        interval = 50
+        duration = 500
+
+        [vehicle]
+        speed = 0
+
+        [[event]]
+        time = 100
+        action = "set_signal"
+        signal = 0
+    "#).unwrap();
+    let mut recorder = TraceRecorder::new(vec![31, 131], vec![2, 12]);
+    HeadlessHost::run_with_settings(&scenario, settings, |host| recorder.record(host));
+    let last = recorder.frames.last().unwrap();
+    assert_eq!(last.panel, [0, 1]);
+    assert!(recorder.frames.iter().all(|frame| frame.sound[0] != AtsSound::Play as i32));
+    assert!(recorder.frames.iter().any(|frame| frame.sound[1] == AtsSound::Play as i32));
+}
+
+#[test]
+fn invalid_layout() {
+    let settings: Settings = toml::from_str(r#"
+        [output.panel]
+        Unknown = 10
+        Signal02 = 300
+        Signal01 = 9
+    "#).unwrap();
+    let report = settings.validate_report(None);
+    assert_eq!(report.issues.len(), 3);
+    assert!(report.issues.iter().all(|issue| issue.section == "output"));
+}
@@ -0,0 +1,101 @@
+//! セッションの記録と再生のテスト
+
+use bveats_rs::record::{replay_session, AtsCall, SessionRecorder};
+use bveats_rs::{AtsVehicleSpec, BveAts};
+use uline_atc::atc::uline_atc::ULineATC;
+use uline_atc::sim::replay::{replay_with_recorded_settings, to_record_header};
+use uline_atc::sim::vehicle::PointMassVehicle;
+
+/// ULineATCで走行したセッションを記録し、記録ファイルの内容を返す
+fn record_session(name: &str) -> String {
+    record_session_with_settings(name, None)
+}
+
+/// 設定ファイルを指定して走行したセッションを記録し、記録ファイルの内容を返す
+/// (設定ファイルを指定した場合はヘッダに記録する)
+fn record_session_with_settings(name: &str, settings: Option<&str>) -> String {
+    let path = std::env::temp_dir().join(format!("uline_replay_test_{name}_{}.log", std::process::id()));
+    let settings_path = std::env::temp_dir().join(format!("uline_replay_test_{name}_{}.toml", std::process::id()));
+    let mut ats = ULineATC::default();
+    let mut recorder = match settings {
+        Some(settings) => {
+            std::fs::write(&settings_path, settings).unwrap();
+            ats.settings_path = Some(settings_path.clone());
+            SessionRecorder::create_with_header(&path, &to_record_header(&settings_path, settings)).unwrap()
+        }
+        None => SessionRecorder::create(&path).unwrap(),
+    };
+    let mut vehicle = PointMassVehicle::default();
+    let mut panel = [0; 256];
+    let mut sound = [0; 256];
+
+    let spec = AtsVehicleSpec { brake_notches: 7, power_notches: 4, ats_notch: 1, b67_notch: 5, cars: 6 };
+    let calls = [
+        AtsCall::Load,
+        AtsCall::SetVehicleSpec(spec),
+        AtsCall::Initialize(2),
+        AtsCall::SetSignal(4),
+        AtsCall::SetReverser(1),
+        AtsCall::SetPower(4),
+    ];
+    for call in calls {
+        recorder.record(call);
+        call.apply(&mut ats);
+    }
+    for _ in 0..2000 {
+        let handles = recorder.record_elapse(vehicle.state(), &mut panel, &mut sound,
+            |state, panel, sound| ats.elapse(state, panel, sound));
+        vehicle.step(handles, 10);
+    }
+    recorder.record(AtsCall::Dispose);
+    ats.dispose();
+    drop(recorder);
+
+    let log = std::fs::read_to_string(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_file(&settings_path);
+    log
+}
+
+#[test]
+fn replay_matches_recording() {
+    let log = record_session("matches");
+    let mismatches = replay_session(&mut ULineATC::default(), &log).unwrap();
+    assert_eq!(mismatches, vec![]);
+}
+
+#[test]
+fn replay_detects_changed_output() {
+    let log = record_session("changed");
+    // ATCブレーキが掛かった最初のElapseの記録を書き換える
+    let (index, line) = log.lines().enumerate()
+        .find(|(_, line)| line.starts_with("E ") && line.contains("| 16 "))
+        .expect("ATCブレーキが記録されていません");
+    let changed = log.lines()
+        .map(|text| if text == line { text.replacen("| 16 ", "| 15 ", 1) } else { text.to_string() })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mismatches = replay_session(&mut ULineATC::default(), &changed).unwrap();
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(mismatches[0].line, index + 1);
+    assert!(mismatches[0].message.starts_with("handles"));
+}
+
+#[test]
+fn replay_rejects_broken_log() {
+    assert!(replay_session(&mut ULineATC::default(), "L\nZZ 1\n").is_err());
+    assert!(replay_session(&mut ULineATC::default(), "E 0 0 0 |").is_err());
+}
+
+#[test]
+fn replay_uses_recorded_settings() {
+    // 速度の出力先を変更した設定で記録する
+    let log = record_session_with_settings("settings", Some("# 速度の出力先\n[output.panel]\nSpeed = 200\n"));
+    assert!(log.starts_with("# # 記録時の設定ファイル: "), "{}", log.lines().next().unwrap());
+    assert_eq!(replay_with_recorded_settings(&log).unwrap(), vec![]);
+    // 記録時と異なる設定では差分が出る
+    assert!(!replay_session(&mut ULineATC::default(), &log).unwrap().is_empty());
+    // 設定が記録されていない場合は再生しない
+    assert!(replay_with_recorded_settings(&record_session("no_settings")).is_err());
+}
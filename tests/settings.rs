@@ -0,0 +1,477 @@
+//! 設定ファイルのテスト
+
+use bveats_rs::{AtsBeaconData, AtsInit, AtsKey, AtsVehicleSpec, BveAts};
+use uline_atc::atc::atc_signal::AtcSignal;
+use uline_atc::atc::uline_atc::ULineATC;
+use uline_atc::settings::{profile::SettingsFile, schema, HisetsuBrake, KeyAction, Settings, TascStationSettings};
+
+#[test]
+fn default_signal_index() {
+    let settings = Settings::default();
+    assert_eq!(settings.signal.get_signal(0), Some(AtcSignal::Signal02));
+    assert_eq!(settings.signal.get_signal(4), Some(AtcSignal::Signal45));
+    assert_eq!(settings.signal.get_signal(7), Some(AtcSignal::Signal90));
+    assert_eq!(settings.signal.get_signal(8), Some(AtcSignal::Irekae15));
+    assert_eq!(settings.signal.get_signal(9), Some(AtcSignal::Irekae25));
+    // 対応のない信号インデックスは02信号として扱う
+    assert_eq!(settings.signal.get_signal(10), Some(AtcSignal::Signal02));
+    assert_eq!(settings.signal.get_signal(-1), Some(AtcSignal::Signal02));
+}
+
+#[test]
+fn custom_signal_index() {
+    let settings: Settings = toml::from_str(r#"
+        [signal]
+        unknown_signal = "Signal01"
+
+        [signal.index]
+        10 = "Signal02"
+        11 = "Signal45"
+        20 = "Irekae15"
+        21 = "Irekae25"
+    "#).unwrap();
+    assert_eq!(settings.signal.get_signal(10), Some(AtcSignal::Signal02));
+    assert_eq!(settings.signal.get_signal(11), Some(AtcSignal::Signal45));
+    assert_eq!(settings.signal.get_signal(20), Some(AtcSignal::Irekae15));
+    assert_eq!(settings.signal.get_signal(21), Some(AtcSignal::Irekae25));
+    assert_eq!(settings.signal.get_signal(0), Some(AtcSignal::Signal01));
+}
+
+#[test]
+fn ignore_unknown_signal_index() {
+    let settings: Settings = toml::from_str(r#"
+        [signal]
+        ignore_unknown = true
+    "#).unwrap();
+    assert_eq!(settings.signal.get_signal(3), Some(AtcSignal::Signal25));
+    assert_eq!(settings.signal.get_signal(30), None);
+}
+
+#[test]
+fn hisetsu_brake() {
+    assert_eq!(Settings::default().hisetsu.brake, HisetsuBrake::Full);
+    let settings: Settings = toml::from_str(r#"
+        [hisetsu]
+        brake = "Emergency"
+    "#).unwrap();
+    assert_eq!(settings.hisetsu.brake, HisetsuBrake::Emergency);
+}
+
+#[test]
+fn partial_section_uses_defaults() {
+    // 1項目だけのテーブルも読み込め、記述のない項目はデフォルト値になる
+    let settings: Settings = toml::from_str("[irekae]\nsignal15_speed = 10.0").unwrap();
+    assert_eq!(settings.irekae.signal15_speed, 10.0);
+    assert_eq!(settings.irekae.signal25_speed, Settings::default().irekae.signal25_speed);
+    let settings: Settings = toml::from_str("[atc_release]\nseal = false").unwrap();
+    assert!(!settings.atc_release.seal);
+    assert_eq!(settings.atc_release.key, Settings::default().atc_release.key);
+    let settings: Settings = toml::from_str("[pattern]\nenable = true").unwrap();
+    assert!(settings.pattern.enable);
+    assert_eq!(settings.pattern.deceleration, Settings::default().pattern.deceleration);
+    let settings: Settings = toml::from_str("[caution]\napproach = true").unwrap();
+    assert!(settings.caution.approach);
+    assert_eq!(settings.caution.approach_speed, Settings::default().caution.approach_speed);
+}
+
+#[test]
+fn linear_notch_mapping() {
+    let vehicle = Settings::default().vehicle;
+    assert_eq!(vehicle.get_output_power_notch(4), 31);
+    assert_eq!(vehicle.get_output_power_notch(2), 15);
+    assert_eq!(vehicle.get_output_brake_notch(1), 4);
+    assert_eq!(vehicle.get_output_brake_notch(7), 31);
+    // 入力の非常ブレーキは出力の非常ブレーキ
+    assert_eq!(vehicle.get_output_brake_notch(8), 32);
+    assert_eq!(vehicle.get_output_holding_notch(3), 13);
+    assert_eq!(vehicle.get_half_brake_notch(), 15);
+}
+
+#[test]
+fn custom_notch_mapping() {
+    let settings: Settings = toml::from_str(r#"
+        [vehicle]
+        input_power_notches = 4
+        input_brake_notches = 7
+        output_power_notches = 31
+        output_brake_notches = 31
+        constant_start_speed = 25.0
+        yokusoku_start_speed = 5.0
+        power_notch_map = [0, 10, 18, 25, 31]
+        brake_notch_map = [0, 2, 5, 9, 14, 19, 25, 31]
+        holding_notch_map = [0, 3, 6, 9]
+    "#).unwrap();
+    let vehicle = settings.vehicle;
+    assert_eq!(vehicle.get_output_power_notch(1), 10);
+    assert_eq!(vehicle.get_output_brake_notch(1), 2);
+    assert_eq!(vehicle.get_output_brake_notch(3), 9);
+    assert_eq!(vehicle.get_output_brake_notch(8), 32);
+    assert_eq!(vehicle.get_output_holding_notch(2), 6);
+    // 緩和ブレーキは入力ブレーキの中間(B4)を変換する
+    assert_eq!(vehicle.get_half_brake_notch(), 14);
+    // 対応表の範囲外は端の値を使う
+    assert_eq!(vehicle.get_output_power_notch(5), 31);
+}
+
+/// テストごとの一時ディレクトリに設定ファイルを書き込む
+fn write_temp_settings(name: &str, data: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("uline_{name}_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("uline.toml");
+    std::fs::write(&path, data).unwrap();
+    path
+}
+
+#[test]
+fn reload_settings_by_key_and_initialize() {
+    let path = write_temp_settings("reload", r#"
+        [atc]
+        check_speed_margin = 3.0
+        half_brake_time = 900
+        kakunin_limit_speed = 15.0
+        hijo_limit_speed = 15.0
+    "#);
+    let mut atc = ULineATC::default();
+    atc.settings_path = Some(path.clone());
+    // 更新を検知してInitialize時に再読み込みする
+    atc.initialize(AtsInit::Removed);
+    assert_eq!(atc.settings.atc.check_speed_margin, 3.0);
+
+    // キー操作で再読み込みする
+    std::fs::write(&path, r#"
+        [atc]
+        check_speed_margin = 2.0
+        half_brake_time = 900
+        kakunin_limit_speed = 15.0
+        hijo_limit_speed = 15.0
+    "#).unwrap();
+    atc.key_down(AtsKey::A2);
+    assert_eq!(atc.settings.atc.check_speed_margin, 2.0);
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn reload_settings_keeps_current_on_error() {
+    let path = write_temp_settings("reload_error", r#"
+        [vehicle]
+        input_power_notches = 0
+        input_brake_notches = 7
+        output_power_notches = 31
+        output_brake_notches = 31
+        constant_start_speed = 25.0
+        yokusoku_start_speed = 5.0
+    "#);
+    let mut atc = ULineATC::default();
+    atc.settings_path = Some(path.clone());
+    // 検証に失敗した場合は現在の設定を維持する
+    assert!(!atc.reload_settings());
+    assert_eq!(atc.settings.vehicle.input_power_notches, 4);
+    // パースに失敗した場合も現在の設定を維持する
+    std::fs::write(&path, "[atc").unwrap();
+    assert!(!atc.reload_settings());
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+const PROFILE_SETTINGS: &str = r#"
+    default_profile = "3000"
+
+    [vehicle]
+    input_power_notches = 4
+    input_brake_notches = 7
+    output_power_notches = 31
+    output_brake_notches = 31
+    constant_start_speed = 25.0
+    yokusoku_start_speed = 5.0
+
+    [ato]
+    target_speed = 3.0
+    p2_check_speed = 25.0
+    p3_check_speed = 5.0
+    p4_brake_time = 8000
+    p5_lower_limit_speed = 35.0
+
+    [[profile]]
+    name = "3000"
+    brake_notches = 7
+    power_notches = 4
+    beacon = 1
+
+    [[profile]]
+    name = "6000"
+    brake_notches = 8
+    power_notches = 5
+    beacon = 2
+    [profile.vehicle]
+    input_power_notches = 5
+    input_brake_notches = 8
+    [profile.ato]
+    target_speed = 5.0
+"#;
+
+#[test]
+fn profile_overrides_base_settings() {
+    let file = SettingsFile::parse(PROFILE_SETTINGS).unwrap();
+    assert_eq!(file.profiles.len(), 2);
+    // プロファイルに記述した項目だけを上書きする
+    let settings = file.get_settings(Some("6000")).unwrap();
+    assert_eq!(settings.vehicle.input_brake_notches, 8);
+    assert_eq!(settings.vehicle.output_brake_notches, 31);
+    assert_eq!(settings.ato.target_speed, 5.0);
+    assert_eq!(settings.ato.p2_check_speed, Settings::default().ato.p2_check_speed);
+    assert_eq!(file.get_settings(None).unwrap().ato.target_speed, 3.0);
+
+    let spec = AtsVehicleSpec { brake_notches: 8, power_notches: 5, ats_notch: 1, b67_notch: 5, cars: 6 };
+    assert_eq!(file.select_by_spec(&spec).unwrap().name, "6000");
+    // 一致するプロファイルがない場合はデフォルトのプロファイル
+    let spec = AtsVehicleSpec { brake_notches: 9, power_notches: 5, ats_notch: 1, b67_notch: 5, cars: 6 };
+    assert_eq!(file.select_by_spec(&spec).unwrap().name, "3000");
+    assert_eq!(file.select_by_beacon(2).unwrap().name, "6000");
+    assert!(file.select_by_beacon(3).is_none());
+    // プロファイルがない場合は従来通り読み込める
+    assert!(SettingsFile::parse("[record]\nenable = true").unwrap().profiles.is_empty());
+}
+
+/// 文書中の `prefix` で始まる行の ```toml のコードブロックを取得する
+fn get_toml_block(text: &str, prefix: &str, heading: &str) -> String {
+    let lines: Vec<&str> = text.lines()
+        .filter_map(|line| line.strip_prefix(prefix))
+        .map(|line| line.strip_prefix(' ').unwrap_or(line))
+        .skip_while(|line| !line.starts_with(heading))
+        .skip_while(|line| *line != "```toml")
+        .skip(1)
+        .take_while(|line| *line != "```")
+        .collect();
+    assert!(!lines.is_empty(), "{heading} の例がありません");
+    lines.join("\n")
+}
+
+#[test]
+fn documented_profile_examples_parse() {
+    // モジュールのドキュメントとREADMEの例は、記述した項目だけで読み込める
+    let module_doc = get_toml_block(include_str!("../src/settings/profile.rs"), "//!", "車両プロファイル");
+    let file = SettingsFile::parse(&module_doc).unwrap();
+    assert_eq!(file.get_settings(None).unwrap().vehicle.input_brake_notches, 7);
+    assert_eq!(file.get_settings(Some("6000")).unwrap().vehicle.input_brake_notches, 8);
+    assert_eq!(file.get_settings(Some("6000")).unwrap().vehicle.output_brake_notches, Settings::default().vehicle.output_brake_notches);
+
+    let readme = get_toml_block(include_str!("../README.md"), "", "## 車両プロファイル");
+    let file = SettingsFile::parse(&readme).unwrap();
+    let settings = file.get_settings(Some("6000")).unwrap();
+    assert_eq!((settings.vehicle.input_power_notches, settings.vehicle.input_brake_notches), (5, 8));
+}
+
+#[test]
+fn profile_invalid_settings() {
+    assert!(SettingsFile::parse("default_profile = \"none\"").is_err());
+    assert!(SettingsFile::parse("[[profile]]\nname = \"a\"\n[profile.vehicle]\ninput_brake_notches = \"8\"").is_err());
+}
+
+#[test]
+fn profile_selected_by_vehicle_spec_and_beacon() {
+    let path = write_temp_settings("profile", PROFILE_SETTINGS);
+    let mut atc = ULineATC::default();
+    atc.settings_path = Some(path.clone());
+    assert!(atc.reload_settings());
+    assert_eq!(atc.profile_name.as_deref(), Some("3000"));
+
+    // 車両諸元からプロファイルを選択する
+    atc.set_vehicle_spec(AtsVehicleSpec { brake_notches: 8, power_notches: 5, ats_notch: 1, b67_notch: 5, cars: 6 });
+    assert_eq!(atc.profile_name.as_deref(), Some("6000"));
+    assert_eq!(atc.settings.vehicle.input_brake_notches, 8);
+    assert_eq!(atc.settings.ato.target_speed, 5.0);
+    assert!(!atc.settings_error);
+
+    // 地上子でプロファイルを選択する
+    atc.set_beacon_data(AtsBeaconData { beacon_type: 22, signal: 0, distance: 0.0, optional: 1 });
+    assert_eq!(atc.profile_name.as_deref(), Some("3000"));
+    assert_eq!(atc.settings.ato.target_speed, 3.0);
+    // 対応するプロファイルがない場合は切り替えない
+    atc.set_beacon_data(AtsBeaconData { beacon_type: 22, signal: 0, distance: 0.0, optional: 9 });
+    assert_eq!(atc.profile_name.as_deref(), Some("3000"));
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn key_binding_remapping() {
+    let settings: Settings = toml::from_str(r#"
+        [keys]
+        D = "None"
+        B1 = "HijoUnten"
+        A2 = "AtoDeparture"
+    "#).unwrap();
+    assert_eq!(settings.get_key_action(AtsKey::D), KeyAction::None);
+    assert_eq!(settings.get_key_action(AtsKey::B1), KeyAction::HijoUnten);
+    // [keys] の割当ては各機能のキー設定より優先する
+    assert_eq!(settings.get_key_action(AtsKey::A2), KeyAction::AtoDeparture);
+    // 記述のないキーは既定の割当て
+    assert_eq!(settings.get_key_action(AtsKey::E), KeyAction::KakuninUnten);
+    assert_eq!(settings.get_key_action(AtsKey::F), KeyAction::AtcRelease);
+
+    let mut atc = ULineATC::default();
+    atc.settings = settings;
+    atc.key_down(AtsKey::D);
+    assert!(!atc.enable_02hijo_unten);
+    atc.key_down(AtsKey::B1);
+    assert!(atc.enable_02hijo_unten);
+
+    let mut settings = Settings::default();
+    settings.keys.bindings.insert("Z".to_string(), KeyAction::None);
+    assert_eq!(settings.validate_report(None).issues[0].section, "keys");
+}
+
+#[test]
+fn validate_key_conflicts() {
+    // [keys] と各機能のキー設定で、1つのキーに複数の動作を割り当てると検証エラーになる
+    let mut settings = Settings::default();
+    settings.keys.bindings.insert("A2".to_string(), KeyAction::AtoDeparture);
+    settings.atc_release.confirm_key = "F".to_string();
+    let report = settings.validate_report(None);
+    let messages: Vec<&str> = report.issues.iter().map(|issue| issue.message.as_str()).collect();
+    assert_eq!(messages, [
+        "A2 に複数の動作が割り当てられています。(keys.A2 = AtoDeparture, reload.key)",
+        "F に複数の動作が割り当てられています。(atc_release.key, atc_release.confirm_key)",
+    ]);
+    assert!(report.issues.iter().all(|issue| issue.section == "keys"));
+    // 同じ動作の割当てや、割当てなしは問題ない
+    let mut settings = Settings::default();
+    settings.keys.bindings.insert("F".to_string(), KeyAction::AtcRelease);
+    settings.keys.bindings.insert("G".to_string(), KeyAction::None);
+    assert!(settings.validate_report(None).is_ok());
+}
+
+#[test]
+fn validate_default_settings() {
+    let spec = AtsVehicleSpec { brake_notches: 7, power_notches: 4, ats_notch: 1, b67_notch: 5, cars: 6 };
+    assert!(Settings::default().validate_report(Some(&spec)).is_ok());
+}
+
+#[test]
+fn validate_invalid_settings() {
+    let mut settings = Settings::default();
+    settings.vehicle.input_brake_notches = 0;
+    settings.atc.half_brake_time = -1;
+    settings.ato.p2_check_speed = -5.0;
+    settings.vehicle.brake_notch_map = vec![0, 5, 3];
+    settings.atc_release.key = "Z".to_string();
+    let report = settings.validate_report(None);
+    let sections: Vec<&str> = report.issues.iter().map(|issue| issue.section.as_str()).collect();
+    assert_eq!(sections, ["vehicle", "vehicle", "vehicle", "atc", "atc_release", "ato"]);
+    assert!(report.to_text().contains("half_brake_time"));
+}
+
+#[test]
+fn validate_signal_index_keys() {
+    let mut settings = Settings::default();
+    settings.signal.index.insert("256".to_string(), AtcSignal::Signal01);
+    settings.signal.index.insert("-1".to_string(), AtcSignal::Signal01);
+    settings.signal.index.insert("abc".to_string(), AtcSignal::Signal01);
+    let report = settings.validate_report(None);
+    assert_eq!(report.issues.len(), 3);
+    assert!(report.issues.iter().all(|issue| issue.section == "signal"));
+    assert!(report.to_text().contains("(abc)"));
+}
+
+#[test]
+fn validate_against_vehicle_spec() {
+    let spec = AtsVehicleSpec { brake_notches: 8, power_notches: 5, ats_notch: 1, b67_notch: 5, cars: 6 };
+    let report = Settings::default().validate_report(Some(&spec));
+    assert_eq!(report.issues.len(), 2);
+}
+
+#[test]
+fn settings_error_report_and_indicator() {
+    let path = write_temp_settings("report", "");
+    let report_path = path.with_file_name("uline_settings_report.txt");
+    let mut atc = ULineATC::default();
+    atc.settings_path = Some(path.clone());
+    atc.settings.atc.half_brake_time = -1;
+    atc.set_vehicle_spec(AtsVehicleSpec { brake_notches: 7, power_notches: 4, ats_notch: 1, b67_notch: 5, cars: 6 });
+    // 異常表示とレポートを出力する
+    assert!(atc.settings_error);
+    let report = std::fs::read_to_string(&report_path).unwrap();
+    assert!(report.contains("[atc] half_brake_time"));
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn generated_default_toml_round_trip() {
+    let text = schema::to_default_toml();
+    let settings: Settings = toml::from_str(&text).expect("生成した設定ファイルが読み込めません");
+    assert_eq!(format!("{settings:?}"), format!("{:?}", Settings::default()));
+    assert!(text.contains("# 緩和ブレーキの長さ [ms]\nhalf_brake_time = 900\n"));
+    // f32の値は最短の表記で出力する
+    assert!(text.contains("\nlearning_rate = 0.1\n"));
+}
+
+#[test]
+fn generated_default_toml_items_can_be_removed_or_enabled() {
+    let text = schema::to_default_toml();
+    // 項目やテーブルを削除してもデフォルト値が使われる
+    let removed: Vec<&str> = text
+        .lines()
+        .filter(|line| !line.starts_with("half_brake_time"))
+        .scan(false, |in_tasc, line| {
+            if line.starts_with('[') {
+                *in_tasc = line == "[tasc]";
+            }
+            Some((*in_tasc, line))
+        })
+        .filter(|(in_tasc, _)| !in_tasc)
+        .map(|(_, line)| line)
+        .collect();
+    let file = SettingsFile::parse(&removed.join("\n")).unwrap();
+    assert_eq!(format!("{:?}", file.get_settings(None).unwrap()), format!("{:?}", Settings::default()));
+    // コメントの出力名・キー割当て・車両プロファイルの記述例は、そのまま有効にできる
+    let enabled = text
+        .replace("# default_profile = ", "default_profile = ")
+        .replace("# panel.Speed = ", "panel.Speed = ")
+        .replace("# S = \"AtoDeparture\"", "S = \"None\"");
+    let (base, profile) = enabled.split_once("# [[profile]]").unwrap();
+    let profile: Vec<&str> = profile.lines().map(|line| line.strip_prefix("# ").unwrap_or(line)).collect();
+    let file = SettingsFile::parse(&format!("{base}[[profile]]{}", profile.join("\n"))).unwrap();
+    assert_eq!(file.default_profile.as_deref(), Some("6000"));
+    let settings = file.get_settings(Some("6000")).unwrap();
+    assert_eq!(settings.vehicle.input_brake_notches, 8);
+    assert_eq!(settings.output.panel.get("Speed"), Some(&49));
+    assert_eq!(settings.keys.bindings.get("S"), Some(&KeyAction::None));
+}
+
+#[test]
+fn schema_covers_all_sections() {
+    let sections = schema::get_schema();
+    let defaults = toml::Value::try_from(Settings::default()).unwrap();
+    let table = defaults.as_table().unwrap();
+    assert_eq!(sections.len(), table.len());
+    for section in &sections {
+        // 説明のある項目と設定の項目が一致する (展開する項目はキーの名前の一覧で説明する)
+        let mut names: Vec<&str> = section.fields.iter().filter(|field| !field.flatten).map(|field| field.name.as_str()).collect();
+        let mut keys: Vec<&str> = table[&section.name].as_table().unwrap().keys().map(String::as_str).collect();
+        names.sort();
+        keys.sort();
+        assert_eq!(names, keys, "[{}]", section.name);
+        // 全ての項目に説明とデフォルト値がある
+        for field in &section.fields {
+            assert!(!field.description.is_empty(), "[{}] {} に説明がありません", section.name, field.name);
+            assert!(field.flatten || field.default.is_some(), "[{}] {} にデフォルト値がありません", section.name, field.name);
+        }
+    }
+    // 駅ごとの設定の項目は、全ての項目を記述した例の項目と一致する
+    let tasc = sections.iter().find(|section| section.name == "tasc").unwrap();
+    let stations = tasc.tables.iter().find(|table| table.name == "stations").unwrap();
+    let station = TascStationSettings { p1_distance: Some(1.0), p2_distance: Some(1.0), p90_distance: Some(1.0) };
+    let mut names: Vec<&str> = stations.fields.iter().map(|field| field.name.as_str()).collect();
+    let mut keys: Vec<String> = toml::Value::try_from(station).unwrap().as_table().unwrap().keys().cloned().collect();
+    names.sort();
+    keys.sort();
+    assert_eq!(names, keys);
+    let atc = sections.iter().find(|section| section.name == "atc").unwrap();
+    let margin = atc.fields.iter().find(|field| field.name == "check_speed_margin").unwrap();
+    assert_eq!(margin.unit.as_deref(), Some("km/h"));
+    let json = schema::to_json_schema();
+    assert!(json.contains("\"name\": \"half_brake_time\", \"type\": \"integer\""));
+    // 車両プロファイルと出力名・キーの名前の一覧
+    assert!(json.contains("\"name\": \"default_profile\", \"type\": \"string\""));
+    assert!(json.contains("\"name\": \"beacon\", \"type\": \"integer\""));
+    assert!(json.contains("{\"name\": \"Speed\", \"default\": 49}"));
+    assert!(json.contains("{\"name\": \"EmgEmergencyBrake\", \"default\": null}"));
+}
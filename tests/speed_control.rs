@@ -0,0 +1,165 @@
+//! 定速制御/抑速制御のテスト
+
+use bveats_rs::AtsHandles;
+use uline_atc::atc::uline_atc::ULineATC;
+use uline_atc::settings::Settings;
+use uline_atc::sim::host::HeadlessHost;
+use uline_atc::sim::scenario::{Scenario, ScenarioBuilder};
+
+/// 90信号で40km/hから力行し、P4→P3で定速制御を開始するシナリオ
+fn constant_scenario(resistance: f32, events: &str) -> String {
+    ScenarioBuilder::new(50, 40000, 7).vehicle("speed", 40.0).vehicle("resistance", resistance).events(r#"
+        [[event]]
+        time = 0
+        action = "set_power"
+        notch = 4
+
+        [[event]]
+        time = 1000
+        action = "set_power"
+        notch = 3
+    "#).events(events).build()
+}
+
+/// シナリオを実行し、各フレームの(時刻, 速度, 出力ハンドル)を返す
+fn run(scenario: &str) -> (HeadlessHost<ULineATC>, Vec<(i32, f32, AtsHandles)>) {
+    run_with_settings(scenario, Settings::default())
+}
+
+/// 設定を指定してシナリオを実行する
+fn run_with_settings(scenario: &str, settings: Settings) -> (HeadlessHost<ULineATC>, Vec<(i32, f32, AtsHandles)>) {
+    let scenario = Scenario::parse(scenario).expect("シナリオが不正です");
+    let mut frames = Vec::new();
+    let host = HeadlessHost::run_with_settings(&scenario, settings, |host| frames.push((host.state.time, host.state.speed, host.handles)));
+    (host, frames)
+}
+
+/// 定速制御開始時の目標速度を取得する
+fn target_speed(frames: &[(i32, f32, AtsHandles)]) -> f32 {
+    frames.iter().find(|(time, _, _)| *time >= 1000).unwrap().1
+}
+
+#[test]
+fn constant_speed_holds_target() {
+    for resistance in [0.05, 0.8, -0.8] {
+        let (host, frames) = run(&constant_scenario(resistance, ""));
+        let target = target_speed(&frames);
+        assert!(host.ats.is_constant_control);
+        // 勾配があっても目標速度を維持する
+        for (_, speed, _) in frames.iter().filter(|(time, _, _)| *time >= 15000) {
+            assert!((speed - target).abs() < 1.0, "勾配 {resistance}: 目標 {target}km/h に対して {speed}km/h");
+        }
+    }
+}
+
+#[test]
+fn constant_speed_respects_reverser_and_notches() {
+    let (_, frames) = run(&constant_scenario(-0.8, ""));
+    let settings = Settings::default();
+    for (_, _, handles) in frames.iter().filter(|(time, _, _)| *time >= 1000) {
+        assert_eq!(handles.reverser, 1);
+        assert!(handles.power <= settings.vehicle.output_power_notches);
+        assert!(handles.brake <= settings.vehicle.output_brake_notches + 1);
+    }
+}
+
+#[test]
+fn constant_speed_cancel_by_brake() {
+    let (host, _) = run(&constant_scenario(0.05, r#"
+        [[event]]
+        time = 5000
+        action = "set_brake"
+        notch = 1
+    "#));
+    assert!(!host.ats.is_constant_control);
+}
+
+#[test]
+fn constant_speed_cancel_by_reverser() {
+    let (host, frames) = run(&constant_scenario(0.05, r#"
+        [[event]]
+        time = 5000
+        action = "set_reverser"
+        notch = 0
+    "#));
+    assert!(!host.ats.is_constant_control);
+    // 運転士のレバーサ操作をそのまま出力する
+    assert_eq!(frames.last().unwrap().2.reverser, 0);
+}
+
+#[test]
+fn constant_speed_cancel_by_atc_brake() {
+    let (host, _) = run(&constant_scenario(0.05, r#"
+        [[event]]
+        time = 5000
+        action = "set_signal"
+        signal = 2
+    "#));
+    assert!(!host.ats.is_constant_control);
+}
+
+/// 90信号の下り勾配を惰行し、抑速ノッチを投入するシナリオ
+fn holding_scenario(speed: f32, resistance: f32, events: &str) -> String {
+    ScenarioBuilder::new(50, 40000, 7).vehicle("speed", speed).vehicle("resistance", resistance).events(r#"
+        [[event]]
+        time = 500
+        action = "set_power"
+        notch = -1
+    "#).events(events).build()
+}
+
+#[test]
+fn holding_speed_holds_captured_speed() {
+    let (host, frames) = run(&holding_scenario(40.0, -1.0, ""));
+    assert!(host.ats.is_holding_control);
+    // 下り勾配でも抑速投入時の速度を維持する
+    let target = host.ats.holding_base_speed;
+    for (_, speed, handles) in frames.iter().filter(|(time, _, _)| *time >= 15000) {
+        assert!((speed - target).abs() < 1.0, "目標 {target}km/h に対して {speed}km/h");
+        assert_eq!(handles.power, 0);
+        assert!(handles.brake <= Settings::default().vehicle.output_brake_notches / 2);
+    }
+}
+
+#[test]
+fn holding_speed_max_brake_notch() {
+    let mut settings = Settings::default();
+    settings.vehicle.holding_max_brake_notch = 5;
+    let (_, frames) = run_with_settings(&holding_scenario(40.0, -1.0, ""), settings);
+    // 抑速の最大ブレーキノッチを超えて出力しない
+    assert!(frames.iter().all(|(_, _, handles)| handles.brake <= 5));
+    assert!(frames.iter().any(|(_, _, handles)| handles.brake == 5));
+}
+
+#[test]
+fn holding_speed_per_notch_target() {
+    let (host, frames) = run(&holding_scenario(40.0, -1.0, r#"
+        [[event]]
+        time = 10000
+        action = "set_power"
+        notch = -2
+    "#));
+    assert!(host.ats.is_holding_control);
+    // 抑速2ノッチでは抑速投入時の速度から5km/h低い速度を維持する
+    let target = host.ats.holding_base_speed - 5.0;
+    for (_, speed, _) in frames.iter().filter(|(time, _, _)| *time >= 30000) {
+        assert!((speed - target).abs() < 1.0, "目標 {target}km/h に対して {speed}km/h");
+    }
+}
+
+#[test]
+fn holding_speed_air_brake_handoff() {
+    let mut settings = Settings::default();
+    settings.vehicle.holding_speed_offsets = vec![0.0, 5.0, 18.0];
+    let (_, frames) = run_with_settings(&holding_scenario(20.0, 0.05, r#"
+        [[event]]
+        time = 500
+        action = "set_power"
+        notch = -3
+    "#), settings);
+    // 低速になると空気ブレーキに引き継いで停止する
+    let (_, speed, handles) = frames.last().unwrap();
+    assert_eq!(*speed, 0.0);
+    assert_eq!(handles.reverser, 0);
+    assert!(handles.brake > 0);
+}
@@ -0,0 +1,237 @@
+//! TASC(定位置停止制御)のテスト
+
+use uline_atc::atc::uline_atc::ULineATC;
+use uline_atc::ato::brake_model::{BrakeModel, BrakeModelFile};
+use uline_atc::ato::tasc_report::{StopAccuracy, TascReport, TascReportEntry, TascStop};
+use uline_atc::settings::{Settings, TascPattern, TascStationSettings};
+use uline_atc::sim::{host::HeadlessHost, scenario::{Scenario, ScenarioBuilder}};
+
+/// 停車駅から1000m先の停止目標までATOで走行するシナリオ (地上子の位置とoptionalを指定する, 車両の設定を加えられるように組み立てる前の状態で返す)
+fn tasc_scenario(p1: (f64, i32), p2: (f64, i32), events: &str) -> ScenarioBuilder {
+    ScenarioBuilder::new(10, 150000, 5).ato_departure().events(&format!(r#"
+        [[event]]
+        location = {}
+        action = "set_beacon_data"
+        beacon_type = 1
+        optional = {}
+
+        [[event]]
+        location = {}
+        action = "set_beacon_data"
+        beacon_type = 2
+        optional = {}
+    "#, p1.0, p1.1, p2.0, p2.1)).events(events)
+}
+
+/// シナリオを実行して停車後のホストを返す
+fn run(scenario: &str, settings: Settings) -> HeadlessHost<ULineATC> {
+    let scenario = Scenario::parse(scenario).expect("シナリオが不正です");
+    let host = HeadlessHost::run_with_settings(&scenario, settings, |_| {});
+    assert_eq!(host.state.speed, 0.0);
+    host
+}
+
+#[test]
+fn target_distance_priority() {
+    let mut tasc = Settings::default().tasc;
+    // 既定では地上子のoptionalを使用しない
+    assert_eq!(tasc.beacon_distance_unit, 0.0);
+    assert_eq!(tasc.get_target_distance(TascPattern::P1, None, 40000), 350.5);
+    tasc.beacon_distance_unit = 0.01;
+    // 地上子・駅ごとの設定がない場合は既定値
+    assert_eq!(tasc.get_target_distance(TascPattern::P1, None, 0), 350.5);
+    assert_eq!(tasc.get_target_distance(TascPattern::P2, None, 0), 25.5);
+    assert_eq!(tasc.get_target_distance(TascPattern::P90, None, 0), 600.5);
+    // 地上子のoptional (cm単位)
+    assert_eq!(tasc.get_target_distance(TascPattern::P1, None, 40000), 400.0);
+    // 駅ごとの設定は地上子より優先する
+    tasc.stations.insert("3".to_string(), TascStationSettings { p1_distance: Some(420.0), ..Default::default() });
+    assert_eq!(tasc.get_target_distance(TascPattern::P1, Some(3), 40000), 420.0);
+    assert_eq!(tasc.get_target_distance(TascPattern::P2, Some(3), 0), 25.5);
+    assert_eq!(tasc.get_target_distance(TascPattern::P1, Some(4), 40000), 400.0);
+    // 単位を0にした場合はoptionalを使用しない
+    tasc.beacon_distance_unit = 0.0;
+    assert_eq!(tasc.get_target_distance(TascPattern::P1, None, 40000), 350.5);
+}
+
+#[test]
+fn beacon_distance_stops_at_target() {
+    // 既定値と異なる位置の地上子でも、optionalの距離で停止目標に停車する
+    let mut settings = Settings::default();
+    settings.tasc.beacon_distance_unit = 0.01;
+    let location = run(&tasc_scenario((600.0, 40000), (970.0, 3000), "").build(), settings).state.location;
+    assert!((location - 1000.0).abs() < 1.0, "{location}");
+}
+
+#[test]
+fn station_table_stops_at_target() {
+    let mut settings = Settings::default();
+    settings.tasc.stations.insert("5".to_string(), TascStationSettings {
+        p1_distance: Some(400.0),
+        p2_distance: Some(30.0),
+        p90_distance: None,
+    });
+    let station = r#"
+        [[event]]
+        time = 0
+        action = "set_beacon_data"
+        beacon_type = 7
+        optional = 5
+    "#;
+    let host = run(&tasc_scenario((600.0, 0), (970.0, 0), station).build(), settings);
+    let location = host.state.location;
+    assert!((location - 1000.0).abs() < 1.0, "{location}");
+    // 停車駅の距離で停車すると停車駅の設定を解除する
+    assert_eq!(host.ats.ato.tasc_station, None);
+}
+
+#[test]
+fn stop_accuracy_bands() {
+    assert_eq!(StopAccuracy::from_error(0.0), StopAccuracy::Accurate);
+    assert_eq!(StopAccuracy::from_error(-0.35), StopAccuracy::Accurate);
+    assert_eq!(StopAccuracy::from_error(0.5), StopAccuracy::Near);
+    assert_eq!(StopAccuracy::from_error(-1.0), StopAccuracy::Near);
+    assert_eq!(StopAccuracy::from_error(1.2), StopAccuracy::Overrun);
+    assert_eq!(StopAccuracy::from_error(-3.0), StopAccuracy::Short);
+
+    let mut report = TascReport::default();
+    for (station, stop_location) in [(3, 1000.2), (4, 2001.5)] {
+        let stop = TascStop { time: 0, station: Some(station), target_location: station as f64 * 500.0 - 500.0, stop_location };
+        report.push(TascReportEntry { stop, start_station: 17, destination: 1 });
+    }
+    let text = report.to_text();
+    assert!(text.contains("2回停車"), "{text}");
+    assert!(text.contains("0,3,17,1,1000.00,1000.20,+0.20,±35cm以内"), "{text}");
+    assert!(text.contains("0,4,17,1,1500.00,2001.50,+501.50,行き過ぎ"), "{text}");
+}
+
+#[test]
+fn stop_is_evaluated_and_shown() {
+    let events = r#"
+        [[event]]
+        time = 0
+        action = "set_beacon_data"
+        beacon_type = 11
+        optional = 17
+
+        [[event]]
+        time = 0
+        action = "set_beacon_data"
+        beacon_type = 12
+        optional = 1
+
+        [[event]]
+        time = 0
+        action = "set_beacon_data"
+        beacon_type = 7
+        optional = 2
+    "#;
+    let mut settings = Settings::default();
+    settings.tims.display_draw_time = 0;
+    let host = run(&tasc_scenario((649.5, 0), (974.5, 0), events).build(), settings);
+    // 停車1回分の結果が記録される
+    let entries = &host.ats.tasc_report.entries;
+    assert_eq!(entries.len(), 1);
+    let entry = &entries[0];
+    assert_eq!((entry.stop.station, entry.start_station, entry.destination), (Some(2), 17, 1));
+    // 停止目標は地上子を通過した直後の位置から求める
+    assert!((entry.stop.target_location - 1000.0).abs() < 0.1, "{:?}", entry.stop);
+    assert_eq!(entry.stop.stop_location, host.state.location);
+    // 停車中はTIMSに判定と誤差を表示する
+    assert_eq!(host.panel[121], entry.stop.get_accuracy().to_panel());
+    assert_eq!(host.panel[122], (entry.stop.get_error().abs() * 100.0).round() as i32);
+}
+
+#[test]
+fn report_station_falls_back_to_tims_next_station() {
+    // TASC停車駅の地上子がない場合は、TIMSの次の停車駅を駅の列に記録する
+    let events = r#"
+        [[event]]
+        time = 0
+        action = "set_beacon_data"
+        beacon_type = 18
+        optional = 5
+    "#;
+    let host = run(&tasc_scenario((649.5, 0), (974.5, 0), events).build(), Settings::default());
+    let entries = &host.ats.tasc_report.entries;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].stop.station, Some(5));
+    let text = host.ats.tasc_report.to_text();
+    let row = text.lines().last().unwrap();
+    assert_eq!(row.split(',').nth(1), Some("5"), "{text}");
+    // どちらもない場合は空欄にする
+    let host = run(&tasc_scenario((649.5, 0), (974.5, 0), "").build(), Settings::default());
+    assert_eq!(host.ats.tasc_report.entries[0].stop.station, None);
+    let text = host.ats.tasc_report.to_text();
+    assert_eq!(text.lines().last().unwrap().split(',').nth(1), Some(""), "{text}");
+}
+
+
+#[test]
+fn brake_model_learns_deceleration() {
+    let settings = Settings::default().tasc;
+    let mut model = BrakeModel::new(7, 3.5);
+    // 学習前はノッチに比例する
+    assert!((model.get_notch(2.0) - 4.0).abs() < 1e-4);
+    // B4で1.0km/h/sの減速を3秒間続ける
+    for frame in 0..=300 {
+        let time = frame * 10;
+        model.observe(&settings, time, 60.0 - time as f32 / 1000.0, 0, 4, 0.0);
+    }
+    // ノッチを投入した直後は測定しない
+    assert_eq!(model.samples[4], 301 - settings.learning_settle_time as u32 / 10);
+    assert!((model.deceleration[4] - 1.0).abs() < 1e-3, "{:?}", model.deceleration);
+    assert!((model.get_notch(1.0) - 4.0).abs() < 1e-2);
+    // 測定していないノッチは測定したノッチから推定する
+    assert!((model.get_deceleration_table()[2] - 0.5).abs() < 1e-3);
+    assert!((model.get_notch(1.5) - 6.0).abs() < 1e-2);
+    // 力行中は測定しない
+    let samples = model.samples[4];
+    model.observe(&settings, 3010, 56.99, 1, 4, 0.0);
+    assert_eq!(model.samples[4], samples);
+}
+
+#[test]
+fn adaptive_brake_learns_and_persists() {
+    let dir = std::env::temp_dir().join(format!("uline_brake_model_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("uline.toml");
+    std::fs::write(&path, "[tasc]\nis_old_pattern = false\nadaptive_brake = true\n").unwrap();
+    let model_path = dir.join("uline_brake_model.toml");
+    let _ = std::fs::remove_file(&model_path);
+
+    // 設定より弱いブレーキの車両で走行する
+    let scenario = tasc_scenario((649.5, 0), (974.5, 0), "").vehicle("max_deceleration", 2.5).build();
+    let scenario = Scenario::parse(&scenario).unwrap();
+    let mut host = scenario.create_host(ULineATC::default());
+    host.start(scenario.get_init().unwrap());
+    host.ats.settings_path = Some(path.clone());
+    assert!(host.ats.reload_settings());
+    scenario.play(&mut host, |_| {});
+    assert!((host.state.location - 1000.0).abs() < 1.0, "{}", host.state.location);
+
+    // 測定したノッチは車両の減速度 (出力ノッチ+1で31段, 走行抵抗0.05km/h/s) になる
+    let model = host.ats.ato.brake_model.clone();
+    assert!(model.samples.iter().sum::<u32>() > 0);
+    for (notch, (deceleration, samples)) in model.deceleration.iter().zip(&model.samples).enumerate() {
+        if *samples > 0 {
+            let expected = 2.5 * (notch + 1) as f32 / 31.0 + 0.05;
+            assert!((deceleration - expected).abs() < 0.05, "B{notch}: {deceleration} {expected}");
+        }
+    }
+
+    // 終了時に保存し、次のセッションで読み込む
+    let file = BrakeModelFile::load(&model_path).unwrap();
+    let saved = file.get_model(None, 31).unwrap();
+    assert_eq!((&saved.deceleration, &saved.samples), (&model.deceleration, &model.samples));
+    let mut atc = ULineATC::default();
+    atc.settings_path = Some(path.clone());
+    assert!(atc.reload_settings());
+    assert_eq!((&atc.ato.brake_model.deceleration, &atc.ato.brake_model.samples), (&model.deceleration, &model.samples));
+
+    // 読み込めないファイルは上書きしない (他のプロファイルの学習結果を消さない)
+    std::fs::write(&model_path, "[profiles.\"6000\"").unwrap();
+    atc.save_brake_model();
+    assert_eq!(std::fs::read_to_string(&model_path).unwrap(), "[profiles.\"6000\"");
+    std::fs::remove_dir_all(&dir).unwrap();
+}